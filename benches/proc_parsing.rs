@@ -0,0 +1,96 @@
+//! Micro-benchmarks of the /proc and /sys parsers on captured fixtures.
+//!
+//! Run with `cargo bench --bench proc_parsing`. The parsers are included
+//! from the sources since rgbar has no library target.
+
+use std::{
+    fs,
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+#[allow(dead_code)]
+#[path = "../src/util/fileutil.rs"]
+mod fileutil;
+#[allow(dead_code, unused_imports)]
+#[path = "../src/util/procparse.rs"]
+mod procparse;
+
+use fileutil::ProcFile;
+
+const ITERATIONS: u32 = 100_000;
+
+fn bench(name: &str, mut f: impl FnMut()) {
+    // Warm up the buffers and caches first.
+    for _ in 0..ITERATIONS / 10 {
+        f();
+    }
+
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    let elapsed: Duration = start.elapsed();
+
+    println!(
+        "{:<24} {:>10.1} ns/iter",
+        name,
+        elapsed.as_nanos() as f64 / ITERATIONS as f64
+    );
+}
+
+fn fixture(name: &str) -> String {
+    fs::read_to_string(format!(
+        "{}/benches/fixtures/{}",
+        env!("CARGO_MANIFEST_DIR"),
+        name
+    ))
+    .expect("missing fixture")
+}
+
+fn main() {
+    let stat = fixture("stat");
+    let meminfo = fixture("meminfo");
+    let arcstats = fixture("arcstats");
+    let net_dev = fixture("net_dev");
+
+    let mut cores = Vec::with_capacity(32);
+    bench("proc_stat", || {
+        black_box(procparse::proc_stat(black_box(&stat), &mut cores));
+    });
+    bench("meminfo", || {
+        black_box(
+            procparse::meminfo(black_box(&meminfo))
+                .map(|(_, v)| v)
+                .sum::<usize>(),
+        );
+    });
+    bench("kstat", || {
+        black_box(
+            procparse::kstat(black_box(&arcstats))
+                .find(|(name, _)| *name == "size")
+                .map(|(_, v)| v),
+        );
+    });
+    bench("net_dev", || {
+        black_box(
+            procparse::net_dev(black_box(&net_dev))
+                .map(|(_, down, up)| down + up)
+                .sum::<usize>(),
+        );
+    });
+
+    // Reading the live file, an open ProcFile against opening it every time.
+    if let Ok(mut file) = ProcFile::open("/proc/stat") {
+        bench("read /proc/stat pread", || {
+            black_box(file.read().map(|c| c.len()).unwrap_or_default());
+        });
+        bench("read /proc/stat open", || {
+            black_box(
+                fs::read_to_string("/proc/stat")
+                    .map(|c| c.len())
+                    .unwrap_or_default(),
+            );
+        });
+    }
+}
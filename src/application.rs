@@ -83,10 +83,14 @@ impl RGBApplication {
         }
     }
 
-    pub fn monitor_monitors(screen: &Screen, app: &Application) -> EResult {
-        let app = RGBApplication::new(app).unwrap();
+    pub fn monitor_monitors(screen: &Screen, application: &Application) -> EResult {
+        let app = RGBApplication::new(application).unwrap();
         let app: Rc<RefCell<RGBApplication>> = Rc::new(RefCell::new(app));
 
+        application.connect_shutdown(clone!(@strong app => move |_| {
+            app.borrow().block_manager.shutdown();
+        }));
+
         let display = screen.display();
 
         Self::init_monitor(&app, &display, None);
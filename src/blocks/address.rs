@@ -0,0 +1,225 @@
+use std::{cell::RefCell, net::IpAddr, rc::Rc, thread, time::Duration};
+
+use chin_tools::AResult;
+use nix::poll::PollTimeout;
+
+use crate::config::InterfaceFilter;
+use crate::prelude::*;
+use crate::window::WidgetShareInfo;
+
+use super::netspeed::InterfaceMatcher;
+use super::rtnl::{Link, Rtnl, RtnlEvents};
+use super::Block;
+
+// Used when the notifications can not be subscribed.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Clone)]
+pub enum AddressIn {}
+
+#[derive(Clone)]
+pub enum AddressOut {
+    /// Counted interfaces which are up.
+    Links(Vec<Link>),
+}
+
+pub struct AddressBlock {
+    dualchannel: DualChannel<AddressOut, AddressIn>,
+    filter: InterfaceFilter,
+    /// `false` when rtnetlink is unavailable, the block is hidden then.
+    available: bool,
+}
+
+impl AddressBlock {
+    pub fn new(filter: InterfaceFilter) -> Self {
+        AddressBlock {
+            dualchannel: DualChannel::new(30),
+            filter,
+            available: true,
+        }
+    }
+}
+
+/// The address worth showing: the first IPv4 address of an interface
+/// holding the default route, then any address.
+fn primary_address(links: &[Link]) -> Option<IpAddr> {
+    let mut candidates = links
+        .iter()
+        .filter(|l| l.default_route)
+        .chain(links.iter().filter(|l| !l.default_route))
+        .flat_map(|l| l.addresses.iter().map(|a| a.addr));
+    let first = candidates.clone().next();
+    candidates.find(IpAddr::is_ipv4).or(first)
+}
+
+fn copy_to_clipboard(text: &str) {
+    gtk::Clipboard::get(&gtk::gdk::SELECTION_CLIPBOARD).set_text(text);
+}
+
+fn fill_address_menu(menu: &gtk::Menu, links: &[Link]) {
+    menu.foreach(|child| menu.remove(child));
+
+    for link in links {
+        if link.addresses.is_empty() {
+            continue;
+        }
+        if !menu.children().is_empty() {
+            menu.append(&gtk::SeparatorMenuItem::new());
+        }
+
+        let header = gtk::MenuItem::with_label(&link.name);
+        header.set_sensitive(false);
+        menu.append(&header);
+
+        for addr in link.addresses.iter() {
+            let text = addr.addr.to_string();
+            let item = gtk::MenuItem::with_label(&format!("{}/{}", text, addr.prefix));
+            item.connect_activate(move |_| copy_to_clipboard(&text));
+            menu.append(&item);
+        }
+    }
+
+    menu.show_all();
+}
+
+impl Block for AddressBlock {
+    type Out = AddressOut;
+    type In = AddressIn;
+
+    fn run(&mut self) -> AResult<()> {
+        let mut rtnl = match Rtnl::connect() {
+            Ok(rtnl) => rtnl,
+            Err(err) => {
+                log::error!("unable to connect to rtnetlink, hiding addresses: {}", err);
+                self.available = false;
+                return Ok(());
+            }
+        };
+        let matcher = InterfaceMatcher::new(&self.filter);
+        let sender = self.dualchannel.get_out_sender();
+
+        thread::spawn(move || {
+            let mut events = RtnlEvents::subscribe(&[
+                libc::RTNLGRP_LINK,
+                libc::RTNLGRP_IPV4_IFADDR,
+                libc::RTNLGRP_IPV6_IFADDR,
+                libc::RTNLGRP_IPV4_ROUTE,
+                libc::RTNLGRP_IPV6_ROUTE,
+            ])
+            .map_err(|err| log::warn!("unable to watch addresses, fallback to polling: {}", err))
+            .ok();
+
+            let mut last = None;
+            loop {
+                match rtnl.links() {
+                    Ok(links) => {
+                        let links: Vec<Link> = links
+                            .into_iter()
+                            .filter(|l| l.up && matcher.is_counted(&l.name))
+                            .collect();
+                        if last.as_ref() != Some(&links) {
+                            sender.send(AddressOut::Links(links.clone())).unwrap();
+                            last = Some(links);
+                        }
+                    }
+                    Err(err) => log::error!("unable to dump interfaces: {}", err),
+                }
+
+                let waited = events.as_mut().map(|events| events.wait(PollTimeout::NONE));
+                match waited {
+                    Some(Ok(_)) => {}
+                    Some(Err(err)) => {
+                        log::error!("unable to wait for address events: {}", err);
+                        events = None;
+                    }
+                    None => thread::sleep(POLL_INTERVAL),
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    fn widget(&self, _share_info: &WidgetShareInfo) -> gtk::Widget {
+        let label = gtk::Label::builder().build();
+        label.style_context().add_class("address-label");
+
+        let holder = EventBox::builder().child(&label).build();
+        if !self.available {
+            holder.set_no_show_all(true);
+            return holder.upcast();
+        }
+
+        let links: Rc<RefCell<Vec<Link>>> = Default::default();
+        let menu = gtk::Menu::new();
+        menu.set_attach_widget(Some(&holder));
+        holder.connect_button_release_event(clone!(@strong links => move |_, v1| {
+            match v1.button() {
+                1 => {
+                    if let Some(addr) = primary_address(&links.borrow()) {
+                        copy_to_clipboard(&addr.to_string());
+                    }
+                    Propagation::Stop
+                }
+                3 => {
+                    fill_address_menu(&menu, &links.borrow());
+                    if !menu.children().is_empty() {
+                        menu.popup_at_pointer(Some(v1));
+                    }
+                    Propagation::Stop
+                }
+                _ => Propagation::Proceed,
+            }
+        }));
+
+        let block = holder.clone();
+        let mut receiver = self.dualchannel.get_out_receiver();
+        MainContext::ref_thread_default().spawn_local(async move {
+            loop {
+                if let Ok(msg) = receiver.recv().await {
+                    match msg {
+                        AddressOut::Links(new) => {
+                            let online = new.iter().any(|l| l.default_route);
+                            let style = block.style_context();
+                            if online {
+                                style.remove_class("address-offline");
+                                style.add_class("address-online");
+                            } else {
+                                style.remove_class("address-online");
+                                style.add_class("address-offline");
+                            }
+
+                            match primary_address(&new) {
+                                Some(addr) if online => label.set_label(&addr.to_string()),
+                                _ => label.set_label("offline"),
+                            }
+
+                            let mut tooltip = vec![];
+                            for link in new.iter() {
+                                match link.gateway {
+                                    Some(gateway) => tooltip
+                                        .push(format!("{} (default via {})", link.name, gateway)),
+                                    None if link.default_route => {
+                                        tooltip.push(format!("{} (default)", link.name))
+                                    }
+                                    None => tooltip.push(link.name.clone()),
+                                }
+                                for addr in link.addresses.iter() {
+                                    tooltip.push(format!("  {}/{}", addr.addr, addr.prefix));
+                                }
+                            }
+                            if tooltip.is_empty() {
+                                tooltip.push("no active interface".to_owned());
+                            }
+                            block.set_tooltip_text(Some(tooltip.join("\n").as_str()));
+
+                            links.replace(new);
+                        }
+                    }
+                }
+            }
+        });
+
+        holder.upcast()
+    }
+}
@@ -0,0 +1,157 @@
+use crate::blocks::Block;
+use crate::config::read_config;
+use crate::datahodler::channel::{DualChannel, MSender};
+use crate::prelude::*;
+use crate::util::gtk_icon_loader::{load_fixed_status_surface, StatusName};
+use crate::window::WidgetShareInfo;
+
+use super::pulse::{self, Device};
+use super::{coalesce_steps, connect_volume_scroll, DeviceKind, PulseBM, PulseWM, SoundDevice};
+
+/// The default source, usually the microphone.
+pub struct MicBlock {
+    dualchannel: DualChannel<PulseWM, PulseBM>,
+    /// Moved into the task handling messages by `run`.
+    default_source: Option<Device>,
+}
+
+impl MicBlock {
+    pub fn new() -> Self {
+        let dualchannel: DualChannel<PulseWM, PulseBM> = DualChannel::new(32);
+        let default_source =
+            Device::new(DeviceKind::Source, None, dualchannel.get_in_sender()).unwrap();
+
+        MicBlock {
+            dualchannel,
+            default_source: Some(default_source),
+        }
+    }
+
+    fn vol_changed(sender: &MSender<PulseWM>, device: &Device) {
+        sender
+            .send(PulseWM::Source {
+                muted: device.muted(),
+                vol: device.volume(),
+                live: pulse::recording(),
+            })
+            .unwrap();
+    }
+}
+
+impl Block for MicBlock {
+    type Out = PulseWM;
+
+    type In = PulseBM;
+
+    fn run(&mut self) -> AResult<()> {
+        let receiver = self.dualchannel.get_in_receiver();
+        let sender = self.dualchannel.get_out_sender();
+        let Some(mut default_source) = self.default_source.take() else {
+            return Ok(());
+        };
+        MainContext::ref_thread_default().spawn_local(async move {
+            let mut next = None;
+            loop {
+                let msg = match next.take() {
+                    Some(msg) => msg,
+                    None => match receiver.recv().await {
+                        Ok(msg) => msg,
+                        Err(_) => continue,
+                    },
+                };
+                match msg {
+                    PulseBM::ToggleMute => {
+                        let _ = default_source.toggle().await;
+                    }
+                    PulseBM::SetVolume(v) => {
+                        let limit = read_config(|c| c.audio.mic_max_volume);
+                        let _ = default_source
+                            .set_volume_to(v, Some(limit))
+                            .map_err(|e| log::info!("error: {e}"));
+                    }
+                    PulseBM::SetDefaultSink(_)
+                    | PulseBM::SetSinkPort(..)
+                    | PulseBM::SetAppVolume(..)
+                    | PulseBM::SetAppMute(..)
+                    | PulseBM::MoveApp(..) => {}
+                    PulseBM::Increase(v) | PulseBM::Decrease(v) => {
+                        let step = match msg {
+                            PulseBM::Decrease(_) => -(v as i32),
+                            _ => v as i32,
+                        };
+                        let (step, rest) = coalesce_steps(&receiver, step);
+                        next = rest;
+
+                        let limit = read_config(|c| c.audio.mic_max_volume);
+                        let _ = default_source
+                            .set_volume(step, Some(limit))
+                            .map_err(|e| log::info!("error: {e}"));
+                    }
+                    PulseBM::GetVolume => {
+                        default_source.get_info().await.unwrap();
+                        Self::vol_changed(&sender, &default_source)
+                    }
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    fn widget(&self, _: &WidgetShareInfo) -> gtk::Widget {
+        let holder = gtk::Box::builder()
+            .orientation(gtk::Orientation::Horizontal)
+            .build();
+
+        let mic_icon =
+            gtk::Image::from_surface(load_fixed_status_surface(StatusName::Microphone).as_ref());
+        let volume = gtk::Label::builder().build();
+        holder.pack_start(&mic_icon, false, false, 0);
+        holder.pack_start(&volume, false, false, 0);
+
+        let holder = EventBox::builder().child(&holder).build();
+
+        let block = holder.clone();
+        let mut receiver = self.dualchannel.get_out_receiver();
+        MainContext::ref_thread_default().spawn_local(async move {
+            loop {
+                if let Ok(PulseWM::Source { muted, vol, live }) = receiver.recv().await {
+                    let status = match muted {
+                        true => StatusName::MicrophoneMute,
+                        false => StatusName::Microphone,
+                    };
+                    mic_icon.set_from_surface(load_fixed_status_surface(status).as_ref());
+                    volume.set_text(format!(" {}%", vol).as_str());
+
+                    // A muted microphone is not heard even when captured.
+                    let style = block.style_context();
+                    if live && !muted {
+                        style.add_class("mic-live");
+                    } else {
+                        style.remove_class("mic-live");
+                    }
+                    block.set_tooltip_text(Some(match (live, muted) {
+                        (true, false) => "Microphone is live",
+                        (true, true) => "Microphone is muted while recording",
+                        (false, _) => "Nothing is recording",
+                    }));
+                }
+            }
+        });
+
+        connect_volume_scroll(&holder, self.dualchannel.get_in_sender());
+
+        let sender = self.dualchannel.in_sender.clone();
+        holder.connect_button_release_event(move |_, v1| match v1.button() {
+            1 => {
+                let _ = sender.send_blocking(PulseBM::ToggleMute);
+                Propagation::Stop
+            }
+            _ => Propagation::Proceed,
+        });
+
+        holder.add_events(EventMask::SCROLL_MASK | EventMask::SMOOTH_SCROLL_MASK);
+
+        holder.upcast()
+    }
+}
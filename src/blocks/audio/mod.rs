@@ -1,22 +1,23 @@
+pub mod mic;
 #[allow(dead_code)]
 pub mod pulse;
 
-
+use crate::config::read_config;
 use crate::prelude::*;
 use crate::util::gtk_icon_loader::load_fixed_status_surface;
+use crate::widgets::{mixer_popup::MixerPopup, sink_popup::SinkPopup};
 
 use std::{
-    cell::RefCell,
+    cell::{Cell, RefCell},
     rc::Rc,
-    time::{Duration, SystemTime},
 };
 
 use crate::{
-    datahodler::channel::{DualChannel, MSender},
+    datahodler::channel::{DualChannel, MSender, SReceiver, SSender},
     util::gtk_icon_loader::{self, StatusName},
 };
 
-use self::pulse::Device;
+use self::pulse::{Device, SinkDescription, SinkInput};
 
 use super::Block;
 
@@ -28,6 +29,14 @@ pub enum PulseBM {
     Increase(u32),
     Decrease(u32),
     GetVolume,
+    SetDefaultSink(String),
+    /// Sink and port names.
+    SetSinkPort(String, String),
+    /// Stream index and volume in percent.
+    SetAppVolume(u32, u32),
+    SetAppMute(u32, bool),
+    /// Stream index and sink name.
+    MoveApp(u32, String),
 }
 
 #[derive(Clone)]
@@ -41,6 +50,18 @@ pub enum PulseWM {
         vol: u32,
         device_type: DeviceType,
     }, // Muted, volume, earphone
+    Source {
+        muted: bool,
+        vol: u32,
+        /// Some application is capturing.
+        live: bool,
+    },
+    Sinks {
+        sinks: Vec<SinkDescription>,
+        /// Name of the default sink.
+        default: String,
+    },
+    Apps(Vec<SinkInput>),
 }
 
 #[derive(Clone, Debug)]
@@ -64,6 +85,8 @@ trait SoundDevice {
 
     async fn get_info(&mut self) -> AResult<()>;
     fn set_volume(&self, step: i32, max_vol: Option<u32>) -> AResult<()>;
+    /// Set the loudest channel to `volume` percent.
+    fn set_volume_to(&self, volume: u32, max_vol: Option<u32>) -> AResult<()>;
     async fn toggle(&self) -> AResult<()>;
 }
 
@@ -74,6 +97,48 @@ pub enum DeviceKind {
     Source,
 }
 
+/// Add up the volume steps queued behind `step`, so a burst of scrolling is
+/// one change. The first other message is returned, it is yet to be
+/// handled.
+fn coalesce_steps(receiver: &SReceiver<PulseBM>, mut step: i32) -> (i32, Option<PulseBM>) {
+    while let Ok(msg) = receiver.try_recv() {
+        match msg {
+            PulseBM::Increase(v) => step += v as i32,
+            PulseBM::Decrease(v) => step -= v as i32,
+            msg => return (step, Some(msg)),
+        }
+    }
+    (step, None)
+}
+
+/// Change the volume on scroll. Smooth scroll deltas are added up, a notch
+/// of a wheel or the same distance on a touchpad is one step.
+fn connect_volume_scroll(holder: &EventBox, sender: SSender<PulseBM>) {
+    let step = read_config(|c| c.audio.step);
+    let pending = Cell::new(0f64);
+
+    holder.connect_scroll_event(move |_, v| {
+        let Some((_, dy)) = v.scroll_deltas() else {
+            return Propagation::Proceed;
+        };
+        // Turning back starts over.
+        let total = match pending.get() * dy < 0. {
+            true => dy,
+            false => pending.get() + dy,
+        };
+        let notches = total.trunc();
+        pending.set(total - notches);
+
+        let notches = notches as i32;
+        if notches > 0 {
+            let _ = sender.send_blocking(PulseBM::Increase(notches as u32 * step));
+        } else if notches < 0 {
+            let _ = sender.send_blocking(PulseBM::Decrease(notches.unsigned_abs() * step));
+        }
+        Propagation::Stop
+    });
+}
+
 pub struct PulseBlock {
     dualchannel: DualChannel<PulseWM, PulseBM>,
     default_sink: Rc<RefCell<Device>>,
@@ -90,6 +155,9 @@ impl PulseBlock {
             )
             .unwrap(),
         ));
+        if let Err(err) = pulse::list_sinks().and_then(|_| pulse::list_sink_inputs()) {
+            log::error!("unable to list sinks: {}", err);
+        }
 
         PulseBlock {
             dualchannel,
@@ -142,46 +210,75 @@ impl Block for PulseBlock {
         let receiver = self.dualchannel.get_in_receiver();
         let sender = self.dualchannel.get_out_sender();
         let default_sink = self.default_sink.clone();
-        let mut last_time = SystemTime::now();
         MainContext::ref_thread_default().spawn_local(async move {
+            let mut next = None;
             loop {
-                if let Ok(msg) = receiver.recv().await { match msg {
+                let msg = match next.take() {
+                    Some(msg) => msg,
+                    None => match receiver.recv().await {
+                        Ok(msg) => msg,
+                        Err(_) => continue,
+                    },
+                };
+                match msg {
                     PulseBM::ToggleMute => {
                         let sink = default_sink.borrow();
                         let _ = sink.toggle().await;
                     }
-                    PulseBM::SetVolume(_) => {}
-                    PulseBM::Increase(v) => {
-                        let now = SystemTime::now();
-
-                        if now.duration_since(last_time).unwrap_or_default()
-                            > Duration::from_millis(100)
-                        {
-                            let sink = default_sink.borrow();
-                            let _ = sink
-                                .set_volume(v as i32, Some(150))
-                                .map_err(|e| log::info!("error: {e}"));
-                            last_time = now;
-                        }
+                    PulseBM::SetVolume(v) => {
+                        let limit = read_config(|c| c.audio.volume_limit());
+                        let sink = default_sink.borrow();
+                        let _ = sink
+                            .set_volume_to(v, Some(limit))
+                            .map_err(|e| log::info!("error: {e}"));
                     }
-                    PulseBM::Decrease(v) => {
-                        let now = SystemTime::now();
-
-                        if now.duration_since(last_time).unwrap_or_default()
-                            > Duration::from_millis(100)
-                        {
-                            let sink = default_sink.borrow();
-                            let _ = sink
-                                .set_volume(-(v as i32), Some(150))
-                                .map_err(|e| log::info!("error: {e}"));
-                            last_time = now;
-                        }
+                    PulseBM::Increase(v) | PulseBM::Decrease(v) => {
+                        let step = match msg {
+                            PulseBM::Decrease(_) => -(v as i32),
+                            _ => v as i32,
+                        };
+                        let (step, rest) = coalesce_steps(&receiver, step);
+                        next = rest;
+
+                        let limit = read_config(|c| c.audio.volume_limit());
+                        let sink = default_sink.borrow();
+                        let _ = sink
+                            .set_volume(step, Some(limit))
+                            .map_err(|e| log::info!("error: {e}"));
                     }
                     PulseBM::GetVolume => {
                         default_sink.borrow_mut().get_info().await.unwrap();
-                        Self::vol_changed(&sender, &default_sink.borrow())
+                        Self::vol_changed(&sender, &default_sink.borrow());
+                        sender
+                            .send(PulseWM::Sinks {
+                                sinks: pulse::sinks(),
+                                default: DeviceKind::Sink.default_name().into(),
+                            })
+                            .unwrap();
+                        sender.send(PulseWM::Apps(pulse::sink_inputs())).unwrap();
+                    }
+                    PulseBM::SetDefaultSink(name) => {
+                        let move_streams = read_config(|c| c.audio.move_streams);
+                        let _ = pulse::set_default_sink(name, move_streams)
+                            .map_err(|e| log::info!("error: {e}"));
+                    }
+                    PulseBM::SetSinkPort(sink, port) => {
+                        let _ =
+                            pulse::set_sink_port(sink, port).map_err(|e| log::info!("error: {e}"));
+                    }
+                    PulseBM::SetAppVolume(index, volume) => {
+                        let _ = pulse::set_sink_input_volume(index, volume)
+                            .map_err(|e| log::info!("error: {e}"));
+                    }
+                    PulseBM::SetAppMute(index, mute) => {
+                        let _ = pulse::set_sink_input_mute(index, mute)
+                            .map_err(|e| log::info!("error: {e}"));
                     }
-                } }
+                    PulseBM::MoveApp(index, sink) => {
+                        let _ = pulse::move_sink_input(index, sink)
+                            .map_err(|e| log::info!("error: {e}"));
+                    }
+                }
             }
         });
 
@@ -204,10 +301,23 @@ impl Block for PulseBlock {
         holder.pack_start(&vol_icon, false, false, 0);
         holder.pack_start(&volume, false, false, 0);
 
+        let popup = SinkPopup::new(&holder, self.dualchannel.get_in_sender());
+        let mixer = MixerPopup::new(&holder, self.dualchannel.get_in_sender());
+
+        let sinks_popup = popup.clone();
+        let apps_popup = mixer.clone();
         let mut receiver = self.dualchannel.get_out_receiver();
         MainContext::ref_thread_default().spawn_local(async move {
             loop {
                 if let Ok(msg) = receiver.recv().await {
+                    match &msg {
+                        PulseWM::Sinks { sinks, default } => {
+                            sinks_popup.update(sinks, default);
+                            apps_popup.update_sinks(sinks);
+                        }
+                        PulseWM::Apps(inputs) => apps_popup.update(inputs),
+                        _ => {}
+                    }
                     if let PulseWM::Full {
                         muted,
                         vol,
@@ -246,22 +356,7 @@ impl Block for PulseBlock {
         });
         let holder = EventBox::builder().child(&holder).build();
 
-        let sender = self.dualchannel.in_sender.clone();
-        holder.connect_scroll_event(move |_, v| {
-            if let Some((_, v)) = v.scroll_deltas() {
-                if v > 0.02 {
-                    let _ = sender.send_blocking(PulseBM::Increase(3));
-                    Propagation::Stop
-                } else if v < -0.02 {
-                    let _ = sender.send_blocking(PulseBM::Decrease(3));
-                    Propagation::Stop
-                } else {
-                    Propagation::Proceed
-                }
-            } else {
-                Propagation::Proceed
-            }
-        });
+        connect_volume_scroll(&holder, self.dualchannel.get_in_sender());
 
         let sender = self.dualchannel.in_sender.clone();
         holder.connect_button_release_event(move |_, v1| match v1.button() {
@@ -269,6 +364,14 @@ impl Block for PulseBlock {
                 let _ = sender.send_blocking(PulseBM::ToggleMute);
                 Propagation::Stop
             }
+            2 => {
+                mixer.toggle();
+                Propagation::Stop
+            }
+            3 => {
+                popup.toggle();
+                Propagation::Stop
+            }
             _ => Propagation::Proceed,
         });
 
@@ -279,3 +382,39 @@ impl Block for PulseBlock {
         holder.upcast()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn coalesce_queued_steps() {
+        let (sender, receiver) = async_channel::unbounded();
+        for msg in [
+            PulseBM::Increase(3),
+            PulseBM::Increase(3),
+            PulseBM::Decrease(9),
+            PulseBM::ToggleMute,
+            PulseBM::Increase(3),
+        ] {
+            sender.try_send(msg).unwrap();
+        }
+
+        // Stops at the first other message and hands it back.
+        let (step, rest) = coalesce_steps(&receiver, 3);
+        assert_eq!(step, 0);
+        assert!(matches!(rest, Some(PulseBM::ToggleMute)));
+
+        let (step, rest) = coalesce_steps(&receiver, -3);
+        assert_eq!(step, 0);
+        assert!(rest.is_none());
+    }
+
+    #[test]
+    fn coalesce_nothing_queued() {
+        let (_sender, receiver) = async_channel::unbounded();
+        let (step, rest) = coalesce_steps(&receiver, 6);
+        assert_eq!(step, 6);
+        assert!(rest.is_none());
+    }
+}
@@ -1,5 +1,6 @@
 use std::cell::Cell;
 use std::cmp::{max, min};
+use std::collections::BTreeMap;
 use std::convert::{TryFrom, TryInto};
 use std::io;
 use std::os::fd::{IntoRawFd, RawFd};
@@ -7,14 +8,17 @@ use std::sync::Mutex;
 use std::thread;
 
 use async_channel::Sender;
-use chin_tools::{aanyhow, AnyhowContext};
 use chin_tools::AResult;
+use chin_tools::{aanyhow, AnyhowContext};
 use libc::c_void;
 use pulse::callbacks::ListResult;
 use pulse::context::{
-    introspect::ServerInfo, introspect::SinkInfo, introspect::SourceInfo, subscribe::Facility,
-    subscribe::InterestMaskSet, Context, FlagSet, State as PulseState,
+    introspect::ServerInfo, introspect::SinkInfo, introspect::SinkInputInfo,
+    introspect::SourceInfo, introspect::SourceOutputInfo, subscribe::Facility,
+    subscribe::InterestMaskSet, subscribe::Operation as SubscribeOperation, Context, FlagSet,
+    State as PulseState,
 };
+use pulse::def::PortAvailable;
 use pulse::mainloop::api::MainloopApi;
 use pulse::mainloop::standard::{IterateResult, Mainloop};
 use pulse::proplist::{properties, Proplist};
@@ -32,6 +36,14 @@ static CLIENT: LazyLock<AResult<Client>> = LazyLock::new(Client::new);
 static EVENT_LISTENER: Mutex<Vec<Sender<PulseBM>>> = Mutex::new(Vec::new());
 static DEVICES: LazyLock<Mutex<HashMap<(DeviceKind, String), VolInfo>>> =
     LazyLock::new(Default::default);
+/// Every sink by index, for the output switcher.
+static SINKS: LazyLock<Mutex<BTreeMap<u32, SinkDescription>>> = LazyLock::new(Default::default);
+/// Streams of applications playing, by index.
+static SINK_INPUTS: LazyLock<Mutex<BTreeMap<u32, SinkInput>>> = LazyLock::new(Default::default);
+/// Source of every source output capturing right now, by output index.
+static RECORDINGS: LazyLock<Mutex<HashMap<u32, u32>>> = LazyLock::new(Default::default);
+/// Names of the sources which are not the monitor of a sink, by index.
+static CAPTURE_SOURCES: LazyLock<Mutex<HashMap<u32, String>>> = LazyLock::new(Default::default);
 
 // Default device names
 pub(super) static DEFAULT_SOURCE: Mutex<Cow<'static, str>> =
@@ -55,10 +67,107 @@ pub(super) struct Device {
     form_factor: Option<String>,
     device_kind: DeviceKind,
     volume: Cell<Option<ChannelVolumes>>,
-    volume_avg: Cell<u32>,
+    /// Loudest channel in percent, the one `set_volume_to` scales.
+    volume_max: Cell<u32>,
     muted: Cell<bool>,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SinkPort {
+    pub name: String,
+    pub description: String,
+    /// `false` when the jack is known to be unplugged.
+    pub available: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SinkDescription {
+    pub index: u32,
+    pub name: String,
+    pub description: String,
+    pub active_port: Option<String>,
+    pub ports: Vec<SinkPort>,
+}
+
+impl TryFrom<&SinkInfo<'_>> for SinkDescription {
+    type Error = ();
+
+    fn try_from(sink_info: &SinkInfo) -> std::result::Result<Self, Self::Error> {
+        let name = sink_info.name.as_ref().ok_or(())?.to_string();
+        let ports = sink_info
+            .ports
+            .iter()
+            .filter_map(|port| {
+                let name = port.name.as_ref()?.to_string();
+                Some(SinkPort {
+                    description: port
+                        .description
+                        .as_ref()
+                        .map_or_else(|| name.clone(), |d| d.to_string()),
+                    name,
+                    available: port.available != PortAvailable::No,
+                })
+            })
+            .collect();
+
+        Ok(SinkDescription {
+            index: sink_info.index,
+            description: sink_info
+                .description
+                .as_ref()
+                .map_or_else(|| name.clone(), |d| d.to_string()),
+            name,
+            active_port: sink_info
+                .active_port
+                .as_ref()
+                .and_then(|a| a.name.as_ref().map(|n| n.to_string())),
+            ports,
+        })
+    }
+}
+
+/// A stream an application plays.
+#[derive(Debug, Clone)]
+pub struct SinkInput {
+    pub index: u32,
+    pub app_name: String,
+    pub icon_name: Option<String>,
+    pub binary: Option<String>,
+    /// Index of the sink it plays on.
+    pub sink: u32,
+    /// Loudest channel in percent, the one setting the volume scales to.
+    pub volume: u32,
+    pub muted: bool,
+    volumes: ChannelVolumes,
+}
+
+impl From<&SinkInputInfo<'_>> for SinkInput {
+    fn from(info: &SinkInputInfo) -> Self {
+        let app_name = info
+            .proplist
+            .get_str(properties::APPLICATION_NAME)
+            .or_else(|| info.name.as_ref().map(|n| n.to_string()))
+            .unwrap_or_else(|| format!("Stream {}", info.index));
+
+        SinkInput {
+            index: info.index,
+            app_name,
+            icon_name: info.proplist.get_str(properties::APPLICATION_ICON_NAME),
+            binary: info
+                .proplist
+                .get_str(properties::APPLICATION_PROCESS_BINARY),
+            sink: info.sink,
+            volume: percent(info.volume.max()),
+            muted: info.mute,
+            volumes: info.volume,
+        }
+    }
+}
+
+fn percent(volume: Volume) -> u32 {
+    (volume.0 as f32 / Volume::NORMAL.0 as f32 * 100.0).round() as u32
+}
+
 struct Connection {
     mainloop: Mainloop,
     context: Context,
@@ -127,6 +236,15 @@ enum ClientRequest {
     GetInfoByName(DeviceKind, String),
     SetVolumeByName(DeviceKind, String, ChannelVolumes),
     SetMuteByName(DeviceKind, String, bool),
+    GetSourceOutputs,
+    ListSinks,
+    /// Make a sink the default, and move the playing streams to it if asked.
+    SetDefaultSink(String, bool),
+    SetSinkPort(String, String),
+    MoveSinkInput(u32, String),
+    ListSinkInputs,
+    SetSinkInputVolume(u32, ChannelVolumes),
+    SetSinkInputMute(u32, bool),
 }
 
 impl Connection {
@@ -221,23 +339,57 @@ impl Client {
             ml_waker.attach(connection.mainloop.get_api());
 
             let introspector = connection.context.introspect();
-            connection
-                .context
-                .set_subscribe_callback(Some(Box::new(move |facility, _, index| match facility {
+            connection.context.set_subscribe_callback(Some(Box::new(
+                move |facility, operation, index| match facility {
                     Some(Facility::Server) => {
                         introspector.get_server_info(Client::server_info_callback);
                     }
-                    Some(Facility::Sink) => {
-                        introspector.get_sink_info_by_index(index, Client::sink_info_callback);
-                    }
-                    Some(Facility::Source) => {
-                        introspector.get_source_info_by_index(index, Client::source_info_callback);
-                    }
+                    Some(Facility::Sink) => match operation {
+                        Some(SubscribeOperation::Removed) => {
+                            SINKS.lock().unwrap().remove(&index);
+                            Client::send_update_event();
+                        }
+                        _ => {
+                            introspector.get_sink_info_by_index(index, Client::sink_info_callback);
+                        }
+                    },
+                    Some(Facility::Source) => match operation {
+                        Some(SubscribeOperation::Removed) => {
+                            CAPTURE_SOURCES.lock().unwrap().remove(&index);
+                            Client::send_update_event();
+                        }
+                        _ => {
+                            introspector
+                                .get_source_info_by_index(index, Client::source_info_callback);
+                        }
+                    },
+                    Some(Facility::SinkInput) => match operation {
+                        Some(SubscribeOperation::Removed) => {
+                            SINK_INPUTS.lock().unwrap().remove(&index);
+                            Client::send_update_event();
+                        }
+                        _ => {
+                            introspector
+                                .get_sink_input_info(index, Client::sink_input_info_callback);
+                        }
+                    },
+                    Some(Facility::SourceOutput) => match operation {
+                        Some(SubscribeOperation::Removed) => Client::set_recording(index, None),
+                        _ => {
+                            introspector
+                                .get_source_output_info(index, Client::source_output_info_callback);
+                        }
+                    },
                     _ => (),
-                })));
+                },
+            )));
 
             connection.context.subscribe(
-                InterestMaskSet::SERVER | InterestMaskSet::SINK | InterestMaskSet::SOURCE,
+                InterestMaskSet::SERVER
+                    | InterestMaskSet::SINK
+                    | InterestMaskSet::SOURCE
+                    | InterestMaskSet::SINK_INPUT
+                    | InterestMaskSet::SOURCE_OUTPUT,
                 |_| (),
             );
 
@@ -285,6 +437,43 @@ impl Client {
                         SetMuteByName(DeviceKind::Source, name, mute) => {
                             introspector.set_source_mute_by_name(&name, mute, None);
                         }
+                        GetSourceOutputs => {
+                            introspector
+                                .get_source_output_info_list(Client::source_output_info_callback);
+                        }
+                        ListSinks => {
+                            introspector.get_sink_info_list(Client::sink_info_callback);
+                        }
+                        SetDefaultSink(name, move_inputs) => {
+                            connection.context.set_default_sink(&name, |_| ());
+                            if move_inputs {
+                                introspector.get_sink_input_info_list(
+                                    move |result: ListResult<&SinkInputInfo>| {
+                                        if let ListResult::Item(info) = result {
+                                            let _ = Client::send(MoveSinkInput(
+                                                info.index,
+                                                name.clone(),
+                                            ));
+                                        }
+                                    },
+                                );
+                            }
+                        }
+                        SetSinkPort(sink, port) => {
+                            introspector.set_sink_port_by_name(&sink, &port, None);
+                        }
+                        MoveSinkInput(index, sink) => {
+                            introspector.move_sink_input_by_name(index, &sink, None);
+                        }
+                        ListSinkInputs => {
+                            introspector.get_sink_input_info_list(Client::sink_input_info_callback);
+                        }
+                        SetSinkInputVolume(index, volumes) => {
+                            introspector.set_sink_input_volume(index, &volumes, None);
+                        }
+                        SetSinkInputMute(index, mute) => {
+                            introspector.set_sink_input_mute(index, mute, None);
+                        }
                     };
                 }
             }
@@ -307,12 +496,28 @@ impl Client {
     }
 
     fn server_info_callback(server_info: &ServerInfo) {
-        if let Some(default_sink) = server_info.default_sink_name.as_ref() {
-            *DEFAULT_SINK.lock().unwrap() = default_sink.to_string().into();
-        }
-
-        if let Some(default_source) = server_info.default_source_name.as_ref() {
-            *DEFAULT_SOURCE.lock().unwrap() = default_source.to_string().into();
+        let defaults = [
+            (
+                DeviceKind::Sink,
+                &DEFAULT_SINK,
+                &server_info.default_sink_name,
+            ),
+            (
+                DeviceKind::Source,
+                &DEFAULT_SOURCE,
+                &server_info.default_source_name,
+            ),
+        ];
+        for (kind, default, name) in defaults {
+            let Some(name) = name.as_ref() else {
+                continue;
+            };
+            let mut default = default.lock().unwrap();
+            if *default != *name {
+                *default = name.to_string().into();
+                // Nothing is known about a device plugged in after start.
+                let _ = Client::send(ClientRequest::GetInfoByName(kind, name.to_string()));
+            }
         }
 
         Client::send_update_event();
@@ -326,6 +531,12 @@ impl Client {
     }
 
     fn sink_info_callback(result: ListResult<&SinkInfo>) {
+        if let ListResult::Item(info) = &result {
+            if let Ok(sink) = SinkDescription::try_from(*info) {
+                SINKS.lock().unwrap().insert(info.index, sink);
+            }
+        }
+
         if let Some(vol_info) = Self::get_info_callback(result) {
             DEVICES
                 .lock()
@@ -337,6 +548,14 @@ impl Client {
     }
 
     fn source_info_callback(result: ListResult<&SourceInfo>) {
+        if let ListResult::Item(info) = &result {
+            let mut sources = CAPTURE_SOURCES.lock().unwrap();
+            match (info.monitor_of_sink, info.name.as_ref()) {
+                (None, Some(name)) => sources.insert(info.index, name.to_string()),
+                _ => sources.remove(&info.index),
+            };
+        }
+
         if let Some(vol_info) = Self::get_info_callback(result) {
             DEVICES
                 .lock()
@@ -347,6 +566,42 @@ impl Client {
         }
     }
 
+    fn sink_input_info_callback(result: ListResult<&SinkInputInfo>) {
+        if let ListResult::Item(info) = result {
+            SINK_INPUTS
+                .lock()
+                .unwrap()
+                .insert(info.index, SinkInput::from(info));
+
+            Client::send_update_event();
+        }
+    }
+
+    fn source_output_info_callback(result: ListResult<&SourceOutputInfo>) {
+        if let ListResult::Item(info) = result {
+            // Level meters such as pavucontrol's open peak detecting streams,
+            // they do not make the microphone live.
+            let peaks = info.resample_method.as_deref() == Some("peaks");
+            let live = !info.corked && !peaks;
+            Client::set_recording(info.index, live.then_some(info.source));
+        }
+    }
+
+    /// Record the source `index` captures from, `None` when it does not.
+    fn set_recording(index: u32, source: Option<u32>) {
+        let changed = {
+            let mut recordings = RECORDINGS.lock().unwrap();
+            match source {
+                Some(source) => recordings.insert(index, source) != Some(source),
+                None => recordings.remove(&index).is_some(),
+            }
+        };
+
+        if changed {
+            Client::send_update_event();
+        }
+    }
+
     fn send_update_event() {
         EVENT_LISTENER
             .lock()
@@ -378,11 +633,14 @@ impl Device {
             form_factor: None,
             device_kind,
             volume: Default::default(),
-            volume_avg: Cell::new(0),
+            volume_max: Cell::new(0),
             muted: Cell::default(),
         };
 
         Client::send(ClientRequest::GetInfoByName(device_kind, device.name()))?;
+        if device_kind == DeviceKind::Source {
+            Client::send(ClientRequest::GetSourceOutputs)?;
+        }
 
         Ok(device)
     }
@@ -395,14 +653,76 @@ impl Device {
 
     fn volume(&self, volume: ChannelVolumes) {
         self.volume.set(Some(volume));
-        self.volume_avg
-            .set((volume.avg().0 as f32 / Volume::NORMAL.0 as f32 * 100.0).round() as u32);
+        self.volume_max.set(percent(volume.max()));
     }
 }
 
+/// Known sinks, ordered by index.
+pub fn sinks() -> Vec<SinkDescription> {
+    SINKS.lock().unwrap().values().cloned().collect()
+}
+
+pub(super) fn list_sinks() -> AResult<()> {
+    Client::send(ClientRequest::ListSinks)
+}
+
+pub(super) fn set_default_sink(name: String, move_inputs: bool) -> AResult<()> {
+    Client::send(ClientRequest::SetDefaultSink(name, move_inputs))
+}
+
+pub(super) fn set_sink_port(sink: String, port: String) -> AResult<()> {
+    Client::send(ClientRequest::SetSinkPort(sink, port))
+}
+
+/// Playing streams, ordered by index.
+pub fn sink_inputs() -> Vec<SinkInput> {
+    SINK_INPUTS.lock().unwrap().values().cloned().collect()
+}
+
+pub(super) fn list_sink_inputs() -> AResult<()> {
+    Client::send(ClientRequest::ListSinkInputs)
+}
+
+/// Set the loudest channel of a stream to `volume` percent.
+pub(super) fn set_sink_input_volume(index: u32, volume: u32) -> AResult<()> {
+    let mut volumes = SINK_INPUTS
+        .lock()
+        .unwrap()
+        .get(&index)
+        .map(|input| input.volumes)
+        .context("Stream unknown")?;
+    let volume = (volume as f32 * Volume::NORMAL.0 as f32 / 100.0).round() as u32;
+
+    // Scaling keeps the balance between the channels.
+    volumes
+        .scale(Volume(min(volume, Volume::MAX.0)))
+        .context("Invalid volume")?;
+    Client::send(ClientRequest::SetSinkInputVolume(index, volumes))
+}
+
+pub(super) fn set_sink_input_mute(index: u32, mute: bool) -> AResult<()> {
+    Client::send(ClientRequest::SetSinkInputMute(index, mute))
+}
+
+pub(super) fn move_sink_input(index: u32, sink: String) -> AResult<()> {
+    Client::send(ClientRequest::MoveSinkInput(index, sink))
+}
+
+/// Whether any application is capturing from the default source. Captures
+/// of sink monitors, e.g. by screen recorders and visualizers, do not count.
+pub(super) fn recording() -> bool {
+    let default = DeviceKind::Source.default_name();
+    let sources = CAPTURE_SOURCES.lock().unwrap();
+    RECORDINGS
+        .lock()
+        .unwrap()
+        .values()
+        .any(|source| sources.get(source).is_some_and(|name| *name == default))
+}
+
 impl SoundDevice for Device {
     fn volume(&self) -> u32 {
-        self.volume_avg.get()
+        self.volume_max.get()
     }
 
     fn muted(&self) -> bool {
@@ -469,6 +789,27 @@ impl SoundDevice for Device {
         Ok(())
     }
 
+    fn set_volume_to(&self, volume: u32, max_vol: Option<u32>) -> AResult<()> {
+        let mut volumes = self.volume.get().context("Volume unknown")?;
+
+        let volume = max_vol.map_or(volume, |vol_cap| min(volume, vol_cap));
+        let volume = (volume as f32 * Volume::NORMAL.0 as f32 / 100.0).round() as u32;
+        // Scaling keeps the balance between the channels.
+        volumes
+            .scale(Volume(min(volume, Volume::MAX.0)))
+            .context("Invalid volume")?;
+
+        Client::send(ClientRequest::SetVolumeByName(
+            self.device_kind,
+            self.name(),
+            volumes,
+        ))?;
+
+        self.volume(volumes);
+
+        Ok(())
+    }
+
     async fn toggle(&self) -> AResult<()> {
         self.muted.set(!self.muted.get());
 
@@ -0,0 +1,247 @@
+use std::{process::Command, thread};
+
+use notify_rust::{Notification, Timeout};
+
+use crate::config::{AlertUrgency, BatteryConfig};
+use crate::util::timeutil::second_to_human;
+
+use super::batdiff::{seconds_now, Remain};
+use super::{BatteryInfo, PowerStatus};
+
+/// An alert to fire, by its position in `config.alerts`.
+#[derive(Debug, PartialEq, Eq)]
+struct Firing {
+    index: usize,
+    /// Fired for the first time at this level.
+    first: bool,
+}
+
+/// Fires the notifications configured in `battery`.
+pub(super) struct Alerts {
+    config: BatteryConfig,
+    /// When each of `config.alerts` last fired, `None` while its level is
+    /// not reached.
+    fired: Vec<Option<usize>>,
+    charged_fired: bool,
+    last_ac_online: Option<bool>,
+}
+
+impl Alerts {
+    pub fn new(config: BatteryConfig) -> Self {
+        Self {
+            fired: vec![None; config.alerts.len()],
+            config,
+            charged_fired: false,
+            last_ac_online: None,
+        }
+    }
+
+    pub fn check(&mut self, battery_info: &BatteryInfo, remain: Option<Remain>) {
+        let percent = battery_info.get_percent();
+        let discharging = battery_info.status == PowerStatus::Discharging;
+        let remain_secs = match remain {
+            Some(Remain::Empty(secs)) => Some(secs),
+            _ => None,
+        };
+
+        for firing in self.due(percent, discharging, remain_secs, seconds_now()) {
+            let alert = &self.config.alerts[firing.index];
+            let remain = remain_secs.map_or_else(|| "unknown".to_owned(), second_to_human);
+            let body = alert
+                .message
+                .replace("{percent}", &percent.to_string())
+                .replace("{remain}", &remain);
+            notify("Low Battery", &body, "battery-low", alert.urgency);
+
+            // Only once per level, not on every repeat.
+            if firing.first {
+                run_command(&alert.command);
+            }
+        }
+
+        if let Some(target) = self.config.charged_percent {
+            let charging = matches!(
+                battery_info.status,
+                PowerStatus::Charging | PowerStatus::Full
+            );
+            if !charging {
+                self.charged_fired = false;
+            } else if percent >= target && !self.charged_fired {
+                notify(
+                    "Battery Charged",
+                    &format!("Charged to {}%, the adapter can be unplugged", percent),
+                    "battery-full",
+                    AlertUrgency::Normal,
+                );
+                self.charged_fired = true;
+            }
+        }
+    }
+
+    /// The alerts to fire now, marking them fired.
+    fn due(
+        &mut self,
+        percent: u8,
+        discharging: bool,
+        remain_secs: Option<usize>,
+        now: usize,
+    ) -> Vec<Firing> {
+        let mut due = vec![];
+        let alerts = self.config.alerts.iter().zip(self.fired.iter_mut());
+        for (index, (alert, fired)) in alerts.enumerate() {
+            let by_percent = alert.percent.map(|p| percent <= p);
+            // Unknown while there is no estimate, e.g. right after a
+            // status change or a suspend.
+            let by_minutes = alert
+                .minutes
+                .map(|minutes| remain_secs.map(|secs| secs as u64 <= minutes * 60));
+            let reached =
+                discharging && (by_percent == Some(true) || by_minutes == Some(Some(true)));
+            if !reached {
+                // Stays armed until the level is known to be left, a new
+                // estimate would fire it and its command again otherwise.
+                if !discharging || by_minutes != Some(None) {
+                    *fired = None;
+                }
+                continue;
+            }
+
+            let first = fired.is_none();
+            let is_due = match *fired {
+                None => true,
+                Some(_) if alert.repeat_secs == 0 => false,
+                Some(last) => now.saturating_sub(last) as u64 >= alert.repeat_secs,
+            };
+            if is_due {
+                due.push(Firing { index, first });
+                *fired = Some(now);
+            }
+        }
+        due
+    }
+
+    pub fn check_ac(&mut self, ac_online: Option<bool>) {
+        let last = std::mem::replace(&mut self.last_ac_online, ac_online);
+        if !self.config.notify_plug {
+            return;
+        }
+
+        match (last, ac_online) {
+            (Some(false), Some(true)) => notify(
+                "Charger Plugged",
+                "Running on AC power",
+                "ac-adapter",
+                AlertUrgency::Low,
+            ),
+            (Some(true), Some(false)) => notify(
+                "Charger Unplugged",
+                "Running on battery",
+                "battery",
+                AlertUrgency::Low,
+            ),
+            _ => {}
+        }
+    }
+}
+
+fn notify(summary: &str, body: &str, icon: &str, urgency: AlertUrgency) {
+    let urgency = match urgency {
+        AlertUrgency::Low => notify_rust::Urgency::Low,
+        AlertUrgency::Normal => notify_rust::Urgency::Normal,
+        AlertUrgency::Critical => notify_rust::Urgency::Critical,
+    };
+    let _ = Notification::new()
+        .summary(summary)
+        .body(body)
+        .icon(icon)
+        .urgency(urgency)
+        .timeout(Timeout::Milliseconds(6000)) //milliseconds
+        .show();
+}
+
+fn run_command(command: &[String]) {
+    let Some((program, args)) = command.split_first() else {
+        return;
+    };
+
+    let mut command = Command::new(program);
+    command.args(args);
+    thread::spawn(move || match command.status() {
+        Ok(status) if status.success() => {}
+        Ok(status) => log::error!("battery alert command exited with {}", status),
+        Err(err) => log::error!("unable to run battery alert command: {}", err),
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::BatteryAlert;
+
+    fn alerts(alert: BatteryAlert) -> Alerts {
+        Alerts::new(BatteryConfig {
+            alerts: vec![alert],
+            ..Default::default()
+        })
+    }
+
+    fn fired(first: bool) -> Vec<Firing> {
+        vec![Firing { index: 0, first }]
+    }
+
+    #[test]
+    fn repeats_while_level_holds() {
+        let mut alerts = alerts(BatteryAlert {
+            percent: Some(30),
+            repeat_secs: 300,
+            ..Default::default()
+        });
+
+        assert_eq!(alerts.due(40, true, None, 1000), vec![]);
+        assert_eq!(alerts.due(30, true, None, 1000), fired(true));
+        assert_eq!(alerts.due(29, true, None, 1200), vec![]);
+        // Repeats do not run the command again.
+        assert_eq!(alerts.due(28, true, None, 1300), fired(false));
+    }
+
+    #[test]
+    fn fires_once_without_repeat() {
+        let mut alerts = alerts(BatteryAlert {
+            percent: Some(30),
+            ..Default::default()
+        });
+
+        assert_eq!(alerts.due(20, true, None, 1000), fired(true));
+        assert_eq!(alerts.due(10, true, None, 100_000), vec![]);
+    }
+
+    #[test]
+    fn rearmed_by_charging() {
+        let mut alerts = alerts(BatteryAlert {
+            percent: Some(30),
+            ..Default::default()
+        });
+
+        assert_eq!(alerts.due(20, true, None, 1000), fired(true));
+        assert_eq!(alerts.due(21, false, None, 1100), vec![]);
+        assert_eq!(alerts.due(21, true, None, 1200), fired(true));
+    }
+
+    #[test]
+    fn stays_armed_without_estimate() {
+        let mut alerts = alerts(BatteryAlert {
+            minutes: Some(10),
+            command: vec!["systemctl".to_owned(), "hibernate".to_owned()],
+            ..Default::default()
+        });
+
+        assert_eq!(alerts.due(20, true, Some(900), 1000), vec![]);
+        assert_eq!(alerts.due(15, true, Some(500), 1100), fired(true));
+        // The estimate is dropped after a suspend and comes back.
+        assert_eq!(alerts.due(15, true, None, 5000), vec![]);
+        assert_eq!(alerts.due(14, true, Some(450), 5100), vec![]);
+        // Known to be above the level, armed again.
+        assert_eq!(alerts.due(14, true, Some(3600), 5200), vec![]);
+        assert_eq!(alerts.due(13, true, Some(300), 5300), fired(true));
+    }
+}
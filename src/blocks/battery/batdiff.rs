@@ -1,8 +1,6 @@
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use notify_rust::{Notification, Timeout};
-
-use crate::{prelude::StatusName, util::timeutil::second_to_human};
+use crate::prelude::StatusName;
 
 use super::{BatteryInfo, PowerStatus};
 
@@ -15,15 +13,21 @@ pub(super) struct BatDiff {
     pub(super) last_percent: u8,
 
     // Remain time
-    pub(super) energy_diff: usize,
-    pub(super) time_diff: usize,
-    pub(super) last_record_seconds: usize,
-    pub(super) last_record_energy: usize,
-    pub(super) last_remain_time_notify_sec: usize,
+    /// Status the rate was measured in, a change starts over.
+    rate_status: PowerStatus,
+    /// Smoothed charge or discharge rate in µW.
+    rate: Option<f64>,
+    rate_updated: Option<Clocks>,
+    /// Energy and time of the last energy change, used when the driver
+    /// reports no power.
+    last_record: Option<(Clocks, u32)>,
+    /// Latest estimate, also between label refreshes.
+    remain: Option<Remain>,
     pub(super) last_remain_time_label_time: usize,
 }
 
-const INTERVAL: usize = 2;
+/// Time for the rate average to mostly forget an old sample.
+const RATE_TIME_CONSTANT: f64 = 60.;
 
 pub fn seconds_now() -> usize {
     SystemTime::now()
@@ -32,7 +36,64 @@ pub fn seconds_now() -> usize {
         .as_secs() as usize
 }
 
+/// Estimated time until the battery is empty or full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Remain {
+    Empty(usize),
+    Full(usize),
+}
+
+/// The monotonic clock stops during suspend while the boot time clock keeps
+/// going, their difference tells a suspend apart from a slow refresh.
+#[derive(Debug, Clone, Copy)]
+struct Clocks {
+    monotonic: Duration,
+    boottime: Duration,
+}
+
+impl Clocks {
+    fn now() -> Self {
+        let clock = |id| {
+            let mut ts = libc::timespec {
+                tv_sec: 0,
+                tv_nsec: 0,
+            };
+            // Safety: `ts` is a valid timespec to write to, both clocks
+            // exist on every kernel we run on.
+            unsafe { libc::clock_gettime(id, &mut ts) };
+            Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32)
+        };
+
+        Self {
+            monotonic: clock(libc::CLOCK_MONOTONIC),
+            boottime: clock(libc::CLOCK_BOOTTIME),
+        }
+    }
+
+    fn elapsed_since(&self, earlier: &Clocks) -> Duration {
+        self.monotonic.saturating_sub(earlier.monotonic)
+    }
+
+    fn suspended_since(&self, earlier: &Clocks) -> bool {
+        let boottime = self.boottime.saturating_sub(earlier.boottime);
+        boottime.saturating_sub(self.elapsed_since(earlier)) > Duration::from_secs(1)
+    }
+}
+
 impl BatDiff {
+    pub fn new() -> Self {
+        Self {
+            last_power_status: PowerStatus::Unknown,
+            last_percent: 0,
+            rate_status: PowerStatus::Unknown,
+            rate: None,
+            rate_updated: None,
+            last_record: None,
+            remain: None,
+            last_remain_time_label_time: 0,
+        }
+    }
+
     pub fn check_percent<F>(&mut self, battery_info: &BatteryInfo, callback: F)
     where
         F: Fn(u8, StatusName),
@@ -71,68 +132,203 @@ impl BatDiff {
             callback(mapped)
         }
     }
+    /// Feed a sample into the smoothed rate. The power reported by the
+    /// driver is preferred, the energy delta is the fallback and only
+    /// moves when the gauge updates.
+    fn update_rate(&mut self, battery_info: &BatteryInfo, now: Clocks) {
+        let energy = battery_info.energy_now;
+        let sample = match self.last_record {
+            // Whatever was drawn during the suspend is not our load.
+            Some((then, _)) if now.suspended_since(&then) => {
+                self.rate = None;
+                self.last_record = Some((now, energy));
+                None
+            }
+            _ if battery_info.power_now > 0 => {
+                self.last_record = Some((now, energy));
+                Some(battery_info.power_now as f64)
+            }
+            Some((then, last_energy)) if energy != last_energy => {
+                let secs = now.elapsed_since(&then).as_secs_f64();
+                self.last_record = Some((now, energy));
+                (secs > 0.).then(|| last_energy.abs_diff(energy) as f64 * 3600. / secs)
+            }
+            Some(_) => None,
+            None => {
+                self.last_record = Some((now, energy));
+                None
+            }
+        };
+
+        let Some(sample) = sample else {
+            return;
+        };
+        // Weighted by the time since the last sample so irregular gauge
+        // updates do not skew the average.
+        self.rate = Some(match (self.rate, self.rate_updated) {
+            (Some(rate), Some(updated)) => {
+                let secs = now.elapsed_since(&updated).as_secs_f64();
+                let alpha = 1. - (-secs / RATE_TIME_CONSTANT).exp();
+                rate + alpha * (sample - rate)
+            }
+            _ => sample,
+        });
+        self.rate_updated = Some(now);
+    }
+
+    pub fn remain(&self) -> Option<Remain> {
+        self.remain
+    }
+
     pub fn check_remain_time<F>(&mut self, battery_info: &BatteryInfo, callback: F)
     where
-        F: Fn(Option<usize>),
+        F: Fn(Option<Remain>),
     {
         let status = battery_info.status;
+        let reset = status != self.rate_status;
+        if reset {
+            self.rate_status = status;
+            self.rate = None;
+            self.rate_updated = None;
+            self.last_record = None;
+            self.remain = None;
+            // Show the new estimate as soon as there is one.
+            self.last_remain_time_label_time = 0;
+        }
 
-        if status != PowerStatus::Discharging {
+        if status != PowerStatus::Discharging && status != PowerStatus::Charging {
+            self.remain = None;
             callback(None);
             return;
         }
 
-        let seconds_now = seconds_now();
+        let secs = match battery_info.remain_secs {
+            // Already estimated by the backend.
+            Some(secs) => secs,
+            None => {
+                let had_rate = self.rate.is_some();
+                self.update_rate(battery_info, Clocks::now());
+                let Some(rate) = self.rate.filter(|rate| *rate >= 1.) else {
+                    // The rate starts over after a status change or a
+                    // suspend, the old estimate no longer holds.
+                    if reset || (had_rate && self.rate.is_none()) {
+                        self.remain = None;
+                        callback(None);
+                    }
+                    return;
+                };
 
-        let time_cost = seconds_now - self.last_record_seconds;
-        self.last_record_seconds = seconds_now;
+                let energy_now = battery_info.energy_now as f64;
+                let energy_left = if status == PowerStatus::Discharging {
+                    energy_now
+                } else {
+                    (battery_info.energy_full as f64 - energy_now).max(0.)
+                };
+                (energy_left * 3600. / rate) as usize
+            }
+        };
+        let remain = if status == PowerStatus::Discharging {
+            Remain::Empty(secs)
+        } else {
+            Remain::Full(secs)
+        };
+        self.remain = Some(remain);
 
-        // We treat this as a sleep time if it is not updated after 60 seconds.
-        if time_cost < INTERVAL + 3 {
-            self.time_diff += time_cost;
+        let seconds_now = seconds_now();
+        if seconds_now.saturating_sub(self.last_remain_time_label_time) > 30 {
+            callback(Some(remain));
+            self.last_remain_time_label_time = seconds_now;
         }
+    }
+}
 
-        let energy_now = battery_info.energy_now as usize;
-        let cap_diff = self.last_record_energy.saturating_sub(energy_now);
-        self.energy_diff += cap_diff;
-
-        self.last_record_energy = energy_now;
-
-        if self.energy_diff > 0 {
-            log::info!(
-                " {} * {} / {}",
-                energy_now,
-                self.time_diff,
-                self.energy_diff
-            );
-            let remain_secs = energy_now * self.time_diff / self.energy_diff;
-            if battery_info.get_percent() < 30
-                && seconds_now - self.last_remain_time_notify_sec > 300
-            {
-                let _ = Notification::new()
-                    .summary("Low Battery")
-                    .body(
-                        format!(
-                            "Connect to adaptar... \nRemain {}% ({})",
-                            battery_info.get_percent(),
-                            second_to_human(remain_secs)
-                        )
-                        .as_str(),
-                    )
-                    .icon("battery-low")
-                    .urgency(notify_rust::Urgency::Critical)
-                    .timeout(Timeout::Milliseconds(6000)) //milliseconds
-                    .show();
-                self.last_remain_time_notify_sec = seconds_now;
-            }
+#[cfg(test)]
+mod test {
+    use std::cell::Cell;
 
-            if seconds_now.saturating_sub(self.last_remain_time_label_time) > 30 {
-                // remain_time.set_label(&format!("({})", second_to_human(remain_secs)));
-                callback(Some(remain_secs));
-                self.last_remain_time_label_time = seconds_now;
-            }
-        } else {
-            callback(None);
+    use super::*;
+    use crate::blocks::battery::common::parse_event;
+
+    fn battery() -> BatteryInfo {
+        parse_event(include_str!("fixtures/thinkpad_t480_bat0.uevent")).unwrap()
+    }
+
+    fn at(monotonic: u64, boottime: u64) -> Clocks {
+        Clocks {
+            monotonic: Duration::from_secs(monotonic),
+            boottime: Duration::from_secs(boottime),
         }
     }
+
+    #[test]
+    fn rate_weighted_by_time() {
+        let mut diff = BatDiff::new();
+        let mut info = battery();
+
+        info.power_now = 10_000_000;
+        diff.update_rate(&info, at(100, 100));
+        assert_eq!(diff.rate, Some(10_000_000.));
+
+        // One time constant later the average moved 1 - 1/e of the way.
+        info.power_now = 20_000_000;
+        diff.update_rate(&info, at(160, 160));
+        let expected = 10_000_000. + (1. - (-1f64).exp()) * 10_000_000.;
+        assert!((diff.rate.unwrap() - expected).abs() < 1.);
+    }
+
+    #[test]
+    fn rate_from_energy_delta() {
+        let mut diff = BatDiff::new();
+        let mut info = battery();
+        info.power_now = 0;
+
+        info.energy_now = 50_000_000;
+        diff.update_rate(&info, at(100, 100));
+        assert_eq!(diff.rate, None);
+
+        // The gauge did not move yet.
+        diff.update_rate(&info, at(110, 110));
+        assert_eq!(diff.rate, None);
+
+        // 10 mWh in 36 s is 1 W.
+        info.energy_now = 49_990_000;
+        diff.update_rate(&info, at(136, 136));
+        assert_eq!(diff.rate, Some(1_000_000.));
+    }
+
+    #[test]
+    fn rate_reset_by_suspend() {
+        let mut diff = BatDiff::new();
+        let mut info = battery();
+
+        info.power_now = 10_000_000;
+        diff.update_rate(&info, at(100, 100));
+        assert!(diff.rate.is_some());
+
+        // Ten seconds awake but an hour passed.
+        diff.update_rate(&info, at(110, 3710));
+        assert_eq!(diff.rate, None);
+
+        // Starts over from the next sample instead of averaging.
+        info.power_now = 5_000_000;
+        diff.update_rate(&info, at(120, 3720));
+        assert_eq!(diff.rate, Some(5_000_000.));
+    }
+
+    #[test]
+    fn status_change_clears_estimate() {
+        let mut diff = BatDiff::new();
+        let mut info = battery();
+        let shown = Cell::new(None);
+
+        diff.check_remain_time(&info, |remain| shown.set(Some(remain)));
+        assert!(matches!(shown.get(), Some(Some(Remain::Empty(_)))));
+
+        // Plugged in, no power reported and no estimate yet.
+        info.status = PowerStatus::Charging;
+        info.power_now = 0;
+        diff.check_remain_time(&info, |remain| shown.set(Some(remain)));
+        assert_eq!(shown.get(), Some(None));
+        assert_eq!(diff.remain(), None);
+    }
 }
@@ -1,18 +1,195 @@
-use chin_tools::AResult;
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
-use crate::util::fileutil;
+use chin_tools::AResult;
 
 use super::PowerStatus::{Charging, Discharging, NotCharging, Unknown};
 
-use super::{BatteryInfo, PowerStatus};
+use super::{BatteryInfo, PowerState, PowerStatus, WarningLevel};
+
+static POWER_SUPPLY_PATH: &str = "/sys/class/power_supply";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SupplyType {
+    Battery,
+    Mains,
+    Other,
+}
+
+/// An entry of /sys/class/power_supply.
+#[derive(Debug, Clone)]
+pub struct PowerSupply {
+    pub path: PathBuf,
+    pub kind: SupplyType,
+    /// Powers a device like a mouse or headset instead of the system.
+    pub peripheral: bool,
+}
+
+impl PowerSupply {
+    fn open(path: PathBuf) -> Option<Self> {
+        let attr = |name: &str| fs::read_to_string(path.join(name)).ok();
+        let kind = match attr("type")?.trim() {
+            "Battery" => SupplyType::Battery,
+            "Mains" => SupplyType::Mains,
+            _ => SupplyType::Other,
+        };
+        let peripheral = attr("scope").is_some_and(|scope| scope.trim() == "Device");
+
+        Some(Self {
+            path,
+            kind,
+            peripheral,
+        })
+    }
+
+    pub fn read(&self) -> AResult<BatteryInfo> {
+        read_event(&self.path.join("uevent"))
+    }
+
+    fn online(&self) -> bool {
+        fs::read_to_string(self.path.join("online")).is_ok_and(|v| v.trim() == "1")
+    }
+}
+
+/// Every power supply, sorted by name so BAT0 comes before BAT1.
+pub fn power_supplies() -> Vec<PowerSupply> {
+    let Ok(entries) = fs::read_dir(POWER_SUPPLY_PATH) else {
+        return vec![];
+    };
+    let mut paths: Vec<PathBuf> = entries.map_while(Result::ok).map(|e| e.path()).collect();
+    paths.sort();
+
+    paths.into_iter().filter_map(PowerSupply::open).collect()
+}
+
+/// Read every supply, enumerated again on each call as adapters, docks and
+/// wireless peripherals come and go.
+pub fn get_power_state() -> PowerState {
+    let mut batteries = vec![];
+    let mut peripherals = vec![];
+    let mut ac_online = None;
+
+    for supply in power_supplies() {
+        match supply.kind {
+            SupplyType::Mains => {
+                ac_online = Some(ac_online.unwrap_or_default() || supply.online());
+            }
+            SupplyType::Battery => match supply.read() {
+                Ok(info) if supply.peripheral => peripherals.push(info),
+                // Empty bays of a two packs laptop are listed too.
+                Ok(info) if info.present == 1 => batteries.push(info),
+                Ok(_) => {}
+                Err(err) => log::warn!("unable to read {:?}: {}", supply.path, err),
+            },
+            SupplyType::Other => {}
+        }
+    }
+
+    let mut battery = combine(batteries);
+    if let Some(info) = battery.as_mut() {
+        // Idle packs report unknown while the adapter powers the system.
+        if ac_online == Some(true) && info.status == PowerStatus::Unknown {
+            info.status = PowerStatus::NotCharging;
+        }
+    }
+
+    PowerState {
+        battery,
+        ac_online,
+        peripherals,
+    }
+}
+
+/// Merge the system batteries into one, summing their energy.
+pub(super) fn combine(mut batteries: Vec<BatteryInfo>) -> Option<BatteryInfo> {
+    if batteries.len() <= 1 {
+        return batteries.pop();
+    }
+
+    let any = |s: PowerStatus| batteries.iter().any(|b| b.status == s);
+    let status = if any(Charging) {
+        Charging
+    } else if any(Discharging) {
+        Discharging
+    } else if batteries.iter().all(|b| b.status == PowerStatus::Full) {
+        PowerStatus::Full
+    } else if any(NotCharging) {
+        NotCharging
+    } else {
+        Unknown
+    };
+
+    let mut combined = batteries[0].clone();
+    combined.name = batteries
+        .iter()
+        .map(|b| b.name.as_str())
+        .collect::<Vec<&str>>()
+        .join("+");
+    combined.status = status;
+    for battery in batteries.iter().skip(1) {
+        combined.power_now += battery.power_now;
+        combined.energy_full_design += battery.energy_full_design;
+        combined.energy_full += battery.energy_full;
+        combined.energy_now += battery.energy_now;
+        combined.cycle_count = combined.cycle_count.max(battery.cycle_count);
+    }
+    combined.capacity = if combined.energy_full > 0 {
+        (combined.energy_now as u64 * 100 / combined.energy_full as u64).min(100) as u8
+    } else {
+        (batteries.iter().map(|b| b.capacity as u32).sum::<u32>() / batteries.len() as u32) as u8
+    };
+
+    Some(combined)
+}
 
-static POWER_INFO_PATH: &str = "/sys/class/power_supply/BAT0/uevent";
+fn read_event(path: &Path) -> AResult<BatteryInfo> {
+    Ok(parse_event(&fs::read_to_string(path)?)?)
+}
 
-pub fn get_battery_info() -> AResult<BatteryInfo> {
-    read_event(POWER_INFO_PATH)
+/// A uevent line we could not make sense of.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UeventError {
+    /// The line has no `=`.
+    MissingSeparator(String),
+    /// The value of a numeric property is not a number.
+    InvalidNumber { key: String, value: String },
 }
 
-fn read_event(_path: &str) -> AResult<BatteryInfo> {
+impl fmt::Display for UeventError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UeventError::MissingSeparator(line) => write!(f, "malformed uevent line: {}", line),
+            UeventError::InvalidNumber { key, value } => {
+                write!(f, "invalid number for {}: {}", key, value)
+            }
+        }
+    }
+}
+
+impl std::error::Error for UeventError {}
+
+fn number<T: FromStr>(key: &str, value: &str) -> Result<T, UeventError> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| UeventError::InvalidNumber {
+            key: key.to_owned(),
+            value: value.to_owned(),
+        })
+}
+
+/// `µAh * µV` or `µA * µV` to `µWh` or `µW`, saturating at `u32::MAX`.
+fn to_energy(charge: u64, voltage: u32) -> u32 {
+    (charge * voltage as u64 / 1_000_000).min(u32::MAX as u64) as u32
+}
+
+/// Parse the content of a power supply uevent file. Drivers report either
+/// energy (µWh, µW) or charge (µAh, µA), the latter is converted to energy
+/// so the rest of the block only deals with one unit.
+pub fn parse_event(content: &str) -> Result<BatteryInfo, UeventError> {
     let mut name: String = "".to_string();
     let mut status: PowerStatus = PowerStatus::Unknown;
     let mut present: u8 = 0;
@@ -20,53 +197,93 @@ fn read_event(_path: &str) -> AResult<BatteryInfo> {
     let mut cycle_count: u32 = 0;
     let mut voltage_min_design: u32 = 0;
     let mut voltage_now: u32 = 0;
-    let mut power_now: u32 = 0;
-    let mut energy_full_design: u32 = 0;
-    let mut energy_full: u32 = 0;
-    let mut energy_now: u32 = 0;
-    let mut capacity: u8 = 0;
+    let mut power_now: Option<u32> = None;
+    let mut energy_full_design: Option<u32> = None;
+    let mut energy_full: Option<u32> = None;
+    let mut energy_now: Option<u32> = None;
+    let mut current_now: Option<u64> = None;
+    let mut charge_full_design: Option<u64> = None;
+    let mut charge_full: Option<u64> = None;
+    let mut charge_now: Option<u64> = None;
+    let mut capacity: Option<u8> = None;
     let mut capacity_level: String = "".to_string();
     let mut model_name: String = "".to_string();
     let mut manufacturer: String = "".to_string();
     let mut serial_numer: String = "".to_string();
 
-    // File hosts must exist in current path before this produces output
-    if let Ok(lines) = fileutil::read_lines(POWER_INFO_PATH) {
-        // Consumes the iterator, returns an (Optional) String
-        for ip in lines.map_while(Result::ok) {
-            let mut kv = ip.split("=");
-            let k = kv.next().unwrap().to_string();
-            let v = kv.next().unwrap().to_string();
-            match k.as_str() {
-                "POWER_SUPPLY_NAME" => name = v.to_string(),
-                "POWER_SUPPLY_STATUS" => {
-                    status = match v.to_lowercase().as_str() {
-                        "charging" => Charging,
-                        "not charging" => NotCharging,
-                        "discharging" => Discharging,
-                        "full" => PowerStatus::Full,
-                        _ => Unknown,
-                    };
-                }
-                "POWER_SUPPLY_PRESENT" => present = v.parse()?,
-                "POWER_SUPPLY_TECHNOLOGY" => technology = v,
-                "POWER_SUPPLY_CYCLE_COUNT" => cycle_count = v.parse()?,
-                "POWER_SUPPLY_VOLTAGE_MIN_DESIGN" => voltage_min_design = v.parse()?,
-                "POWER_SUPPLY_VOLTAGE_NOW" => voltage_now = v.parse()?,
-                "POWER_SUPPLY_POWER_NOW" => power_now = v.parse()?,
-                "POWER_SUPPLY_ENERGY_FULL_DESIGN" => energy_full_design = v.parse()?,
-                "POWER_SUPPLY_ENERGY_FULL" => energy_full = v.parse()?,
-                "POWER_SUPPLY_ENERGY_NOW" => energy_now = v.parse()?,
-                "POWER_SUPPLY_CAPACITY" => capacity = v.parse()?,
-                "POWER_SUPPLY_CAPACITY_LEVEL" => capacity_level = v,
-                "POWER_SUPPLY_MODEL_NAME" => model_name = v,
-                "POWER_SUPPLY_MANUFACTURER" => manufacturer = v,
-                "POWER_SUPPLY_SERIAL_NUMBER" => serial_numer = v,
-                _ => (),
+    for line in content.lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        // Only the first `=` separates, model names and serials may hold
+        // more.
+        let (k, v) = line
+            .split_once('=')
+            .ok_or_else(|| UeventError::MissingSeparator(line.to_owned()))?;
+        let v = v.trim();
+        match k {
+            "POWER_SUPPLY_NAME" => name = v.to_string(),
+            "POWER_SUPPLY_STATUS" => {
+                status = match v.to_lowercase().as_str() {
+                    "charging" => Charging,
+                    "not charging" => NotCharging,
+                    "discharging" => Discharging,
+                    "full" => PowerStatus::Full,
+                    _ => Unknown,
+                };
+            }
+            "POWER_SUPPLY_PRESENT" => present = number(k, v)?,
+            "POWER_SUPPLY_TECHNOLOGY" => technology = v.to_string(),
+            "POWER_SUPPLY_CYCLE_COUNT" => cycle_count = number(k, v)?,
+            "POWER_SUPPLY_VOLTAGE_MIN_DESIGN" => voltage_min_design = number(k, v)?,
+            "POWER_SUPPLY_VOLTAGE_NOW" => voltage_now = number(k, v)?,
+            // Some drivers report a negative power or current while
+            // discharging.
+            "POWER_SUPPLY_POWER_NOW" => {
+                power_now = Some(number::<i64>(k, v)?.unsigned_abs().min(u32::MAX as u64) as u32)
             }
+            "POWER_SUPPLY_CURRENT_NOW" => current_now = Some(number::<i64>(k, v)?.unsigned_abs()),
+            "POWER_SUPPLY_ENERGY_FULL_DESIGN" => energy_full_design = Some(number(k, v)?),
+            "POWER_SUPPLY_ENERGY_FULL" => energy_full = Some(number(k, v)?),
+            "POWER_SUPPLY_ENERGY_NOW" => energy_now = Some(number(k, v)?),
+            "POWER_SUPPLY_CHARGE_FULL_DESIGN" => charge_full_design = Some(number(k, v)?),
+            "POWER_SUPPLY_CHARGE_FULL" => charge_full = Some(number(k, v)?),
+            "POWER_SUPPLY_CHARGE_NOW" => charge_now = Some(number(k, v)?),
+            "POWER_SUPPLY_CAPACITY" => capacity = Some(number(k, v)?),
+            "POWER_SUPPLY_CAPACITY_LEVEL" => capacity_level = v.to_string(),
+            "POWER_SUPPLY_MODEL_NAME" => model_name = v.to_string(),
+            "POWER_SUPPLY_MANUFACTURER" => manufacturer = v.to_string(),
+            "POWER_SUPPLY_SERIAL_NUMBER" => serial_numer = v.to_string(),
+            _ => (),
         }
     }
 
+    // The design voltage is what the charge ratings refer to, the current
+    // one moves with the charge.
+    let voltage = if voltage_min_design > 0 {
+        voltage_min_design
+    } else {
+        voltage_now
+    };
+    let energy = |energy: Option<u32>, charge: Option<u64>| {
+        energy
+            .or_else(|| charge.map(|charge| to_energy(charge, voltage)))
+            .unwrap_or_default()
+    };
+    let energy_full_design = energy(energy_full_design, charge_full_design);
+    let energy_full = energy(energy_full, charge_full);
+    let energy_now = energy(energy_now, charge_now);
+    let power_now = power_now
+        .or_else(|| current_now.map(|current| to_energy(current, voltage_now)))
+        .unwrap_or_default();
+    let capacity = capacity.unwrap_or_else(|| {
+        if energy_full > 0 {
+            (energy_now as u64 * 100 / energy_full as u64).min(100) as u8
+        } else {
+            0
+        }
+    });
+
     Ok(BatteryInfo {
         name,
         status,
@@ -84,5 +301,79 @@ fn read_event(_path: &str) -> AResult<BatteryInfo> {
         model_name,
         manufacturer,
         serial_numer,
+        remain_secs: None,
+        warning: WarningLevel::None,
     })
 }
+
+#[cfg(test)]
+mod test {
+    use super::{parse_event, UeventError};
+    use crate::blocks::battery::PowerStatus;
+
+    #[test]
+    fn energy_based() {
+        let info = parse_event(include_str!("fixtures/thinkpad_t480_bat0.uevent")).unwrap();
+
+        assert_eq!(info.name, "BAT0");
+        assert_eq!(info.status, PowerStatus::Discharging);
+        assert_eq!(info.present, 1);
+        assert_eq!(info.cycle_count, 412);
+        assert_eq!(info.power_now, 7421000);
+        assert_eq!(info.energy_full_design, 24050000);
+        assert_eq!(info.energy_full, 19870000);
+        assert_eq!(info.energy_now, 12540000);
+        assert_eq!(info.capacity, 63);
+        assert_eq!(info.serial_numer, "1234");
+    }
+
+    #[test]
+    fn charge_based() {
+        let info = parse_event(include_str!("fixtures/dell_xps13_bat0.uevent")).unwrap();
+
+        // Charge times the design voltage of 7.6 V.
+        assert_eq!(info.energy_full_design, 50996000);
+        assert_eq!(info.energy_full, 45098400);
+        assert_eq!(info.energy_now, 31312000);
+        // Current times the voltage now of 8.145 V.
+        assert_eq!(info.power_now, 5717790);
+        assert_eq!(info.capacity, 69);
+        assert_eq!(info.model_name, "DELL 4GVGH0A");
+    }
+
+    #[test]
+    fn negative_current_without_capacity() {
+        let info = parse_event(include_str!("fixtures/chromebook_sbs_battery.uevent")).unwrap();
+
+        assert_eq!(info.power_now, 15387500);
+        assert_eq!(info.energy_now, 36971550);
+        // Computed from the energy when the driver has no capacity.
+        assert_eq!(info.capacity, 79);
+        assert_eq!(info.serial_numer, "SN=0042");
+    }
+
+    #[test]
+    fn peripheral() {
+        let info = parse_event(include_str!("fixtures/logitech_hidpp_mouse.uevent")).unwrap();
+
+        assert_eq!(info.model_name, "MX Master 3");
+        assert_eq!(info.capacity, 55);
+        assert_eq!(info.energy_now, 0);
+        assert_eq!(info.power_now, 0);
+    }
+
+    #[test]
+    fn malformed() {
+        assert_eq!(
+            parse_event("POWER_SUPPLY_NAME=BAT0\nPOWER_SUPPLY_STATUS\n").unwrap_err(),
+            UeventError::MissingSeparator("POWER_SUPPLY_STATUS".to_owned())
+        );
+        assert_eq!(
+            parse_event("POWER_SUPPLY_ENERGY_NOW=unknown\n").unwrap_err(),
+            UeventError::InvalidNumber {
+                key: "POWER_SUPPLY_ENERGY_NOW".to_owned(),
+                value: "unknown".to_owned(),
+            }
+        );
+    }
+}
@@ -0,0 +1,293 @@
+use std::{
+    collections::VecDeque,
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::PathBuf,
+};
+
+use chin_tools::AResult;
+
+use crate::util::fileutil::state_dir;
+
+use super::batdiff::seconds_now;
+use super::{BatteryInfo, PowerStatus};
+
+/// A sample is taken at least this often, and whenever the charge or the
+/// status changes.
+pub const SAMPLE_INTERVAL: i64 = 300;
+/// Rewrite the file once this many samples expired, it is only appended
+/// to otherwise.
+const COMPACT_AFTER: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sample {
+    /// Unix time in seconds.
+    pub time: i64,
+    pub percent: u8,
+    pub status: PowerStatus,
+}
+
+impl Sample {
+    /// One line of `time percent status`, e.g. `1718000000 85 D`.
+    fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.split_whitespace();
+        let time = fields.next()?.parse().ok()?;
+        let percent = fields.next()?.parse().ok()?;
+        let status = match fields.next()? {
+            "C" => PowerStatus::Charging,
+            "D" => PowerStatus::Discharging,
+            "F" => PowerStatus::Full,
+            "N" => PowerStatus::NotCharging,
+            _ => PowerStatus::Unknown,
+        };
+        Some(Self {
+            time,
+            percent,
+            status,
+        })
+    }
+
+    fn line(&self) -> String {
+        let status = match self.status {
+            PowerStatus::Charging => "C",
+            PowerStatus::Discharging => "D",
+            PowerStatus::Full => "F",
+            PowerStatus::NotCharging => "N",
+            PowerStatus::Unknown => "U",
+        };
+        format!("{} {} {}\n", self.time, self.percent, status)
+    }
+}
+
+/// Charge level over the last days, kept in
+/// `$XDG_STATE_HOME/rgbar/battery_history`.
+pub struct History {
+    path: Option<PathBuf>,
+    file: Option<File>,
+    samples: VecDeque<Sample>,
+    keep_secs: i64,
+    expired: usize,
+}
+
+impl History {
+    pub fn load(keep_days: u32) -> Self {
+        let path = state_dir().map(|dir| dir.join("battery_history"));
+        Self::load_from(path, keep_days, seconds_now() as i64)
+    }
+
+    fn load_from(path: Option<PathBuf>, keep_days: u32, now: i64) -> Self {
+        let keep_secs = keep_days as i64 * 24 * 3600;
+        let since = now - keep_secs;
+
+        let content = path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .unwrap_or_default();
+        let mut expired = 0;
+        let samples = content
+            .lines()
+            .filter_map(Sample::parse)
+            .filter(|sample| {
+                let keep = sample.time >= since;
+                expired += !keep as usize;
+                keep
+            })
+            .collect();
+
+        let mut history = Self {
+            path,
+            file: None,
+            samples,
+            keep_secs,
+            expired,
+        };
+        if expired > 0 {
+            history.compact();
+        }
+        history
+    }
+
+    pub fn samples(&self) -> &VecDeque<Sample> {
+        &self.samples
+    }
+
+    pub fn record(&mut self, battery_info: &BatteryInfo) {
+        self.record_at(battery_info, seconds_now() as i64);
+    }
+
+    fn record_at(&mut self, battery_info: &BatteryInfo, time: i64) {
+        let sample = Sample {
+            time,
+            percent: battery_info.get_percent(),
+            status: battery_info.status,
+        };
+        let due = match self.samples.back() {
+            Some(last) => {
+                sample.time - last.time >= SAMPLE_INTERVAL
+                    || sample.percent != last.percent
+                    || sample.status != last.status
+            }
+            None => true,
+        };
+        if !due {
+            return;
+        }
+
+        self.samples.push_back(sample);
+        while self
+            .samples
+            .front()
+            .is_some_and(|first| sample.time - first.time > self.keep_secs)
+        {
+            self.samples.pop_front();
+            self.expired += 1;
+        }
+
+        if self.expired >= COMPACT_AFTER {
+            self.compact();
+        } else if let Err(err) = self.append(&sample) {
+            log::error!("unable to save battery history: {}", err);
+        }
+    }
+
+    fn append(&mut self, sample: &Sample) -> AResult<()> {
+        let Some(path) = self.path.as_ref() else {
+            return Ok(());
+        };
+        if self.file.is_none() {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            self.file = Some(OpenOptions::new().create(true).append(true).open(path)?);
+        }
+        if let Some(file) = self.file.as_mut() {
+            file.write_all(sample.line().as_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Drop expired samples from the file.
+    fn compact(&mut self) {
+        let Some(path) = self.path.clone() else {
+            return;
+        };
+        let content: String = self.samples.iter().map(Sample::line).collect();
+
+        // Write aside and rename so a crash never leaves a truncated file.
+        let tmp = path.with_extension("tmp");
+        let result = fs::write(&tmp, content).and_then(|_| fs::rename(tmp, &path));
+        match result {
+            Ok(_) => {
+                self.expired = 0;
+                // The old file is gone, reopen on the next append.
+                self.file = None;
+            }
+            Err(err) => log::error!("unable to save battery history: {}", err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::blocks::battery::common::parse_event;
+
+    fn battery(percent: u8, status: PowerStatus) -> BatteryInfo {
+        let mut info = parse_event(include_str!("fixtures/thinkpad_t480_bat0.uevent")).unwrap();
+        info.capacity = percent;
+        info.status = status;
+        info
+    }
+
+    fn temp_file(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rgbar-history-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn sample_round_trip() {
+        for status in [
+            PowerStatus::Charging,
+            PowerStatus::Discharging,
+            PowerStatus::Full,
+            PowerStatus::NotCharging,
+            PowerStatus::Unknown,
+        ] {
+            let sample = Sample {
+                time: 1718000000,
+                percent: 85,
+                status,
+            };
+            assert_eq!(Sample::parse(&sample.line()), Some(sample));
+        }
+
+        assert_eq!(Sample::parse("1718000000 85 D").unwrap().percent, 85);
+        assert_eq!(
+            Sample::parse("1718000000 85 ?").unwrap().status,
+            PowerStatus::Unknown
+        );
+        assert_eq!(Sample::parse("1718000000 85"), None);
+        assert_eq!(Sample::parse("garbage 85 D"), None);
+    }
+
+    #[test]
+    fn samples_on_change_and_interval() {
+        let mut history = History::load_from(None, 7, 1000);
+
+        history.record_at(&battery(85, PowerStatus::Discharging), 1000);
+        history.record_at(&battery(85, PowerStatus::Discharging), 1100);
+        assert_eq!(history.samples().len(), 1);
+
+        // The charge or the status changed.
+        history.record_at(&battery(84, PowerStatus::Discharging), 1150);
+        history.record_at(&battery(84, PowerStatus::Charging), 1160);
+        assert_eq!(history.samples().len(), 3);
+
+        history.record_at(
+            &battery(84, PowerStatus::Charging),
+            1160 + SAMPLE_INTERVAL - 1,
+        );
+        assert_eq!(history.samples().len(), 3);
+        history.record_at(&battery(84, PowerStatus::Charging), 1160 + SAMPLE_INTERVAL);
+        assert_eq!(history.samples().len(), 4);
+    }
+
+    #[test]
+    fn expired_samples_compacted() {
+        let path = temp_file("expiry");
+        let day = 24 * 3600;
+        let now = 100 * day;
+        let old = Sample {
+            time: now - 8 * day,
+            percent: 90,
+            status: PowerStatus::Discharging,
+        };
+        let recent = Sample {
+            time: now - day,
+            percent: 60,
+            status: PowerStatus::Charging,
+        };
+        fs::write(&path, old.line() + &recent.line() + "garbage\n").unwrap();
+
+        let mut history = History::load_from(Some(path.clone()), 7, now);
+        assert_eq!(history.samples().iter().collect::<Vec<_>>(), vec![&recent]);
+        // Rewritten without the expired sample.
+        assert_eq!(fs::read_to_string(&path).unwrap(), recent.line());
+
+        // Appended to the new file.
+        history.record_at(&battery(70, PowerStatus::Charging), now);
+        let latest = Sample {
+            time: now,
+            percent: 70,
+            status: PowerStatus::Charging,
+        };
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            recent.line() + &latest.line()
+        );
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -1,27 +1,117 @@
-use std::fs;
+use std::{fs, path::PathBuf};
 
-static IDEAPAD_ACPI: &str = "/sys/bus/platform/drivers/ideapad_acpi/VPC2004:00";
+use chin_tools::{aanyhow, AResult};
+use glob::glob;
+
+use crate::util::privileged;
+
+// The ACPI id differs across models, e.g. VPC2004:00 or VPC2004:01.
+static IDEAPAD_ACPI_GLOB: &str = "/sys/bus/platform/drivers/ideapad_acpi/VPC*";
 static CONSERVATION_MODE: &str = "conservation_mode";
-// static FN_LOCK : &str =  format!("{}/{}", IDEAPAD_ACPI, "fn_lock").as_str();
-// static CAMERA_POWER : &str =  format!("{}/{}", IDEAPAD_ACPI, "camera_power").as_str();
-// static FAN_MODE : &str =  format!("{}/{}", IDEAPAD_ACPI, "fan_mode").as_str();
+static FN_LOCK: &str = "fn_lock";
+static CAMERA_POWER: &str = "camera_power";
+static FAN_MODE: &str = "fan_mode";
 
-#[derive(Clone, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ConvervationMode {
     Enable,
     Disable,
     Unknown,
 }
 
-pub fn get_conservation_mode() -> ConvervationMode {
-    match fs::read_to_string(format!("{}/{}", IDEAPAD_ACPI, CONSERVATION_MODE)) {
-        Ok(value) => {
-            if value.starts_with("1") {
-                ConvervationMode::Enable
-            } else {
-                ConvervationMode::Disable
-            }
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FanMode {
+    SuperSilent = 0,
+    Standard = 1,
+    DustCleaning = 2,
+    EfficientThermalDissipation = 4,
+}
+
+impl FanMode {
+    pub const ALL: [FanMode; 4] = [
+        FanMode::SuperSilent,
+        FanMode::Standard,
+        FanMode::DustCleaning,
+        FanMode::EfficientThermalDissipation,
+    ];
+
+    fn from_value(value: u8) -> Option<Self> {
+        Self::ALL.into_iter().find(|mode| *mode as u8 == value)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FanMode::SuperSilent => "Super silent",
+            FanMode::Standard => "Standard",
+            FanMode::DustCleaning => "Dust cleaning",
+            FanMode::EfficientThermalDissipation => "Efficient thermal dissipation",
         }
-        Err(_) => ConvervationMode::Unknown,
+    }
+}
+
+/// Switches of the ideapad_acpi driver, `None` when the model lacks one.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IdeapadExtras {
+    pub fn_lock: Option<bool>,
+    pub camera_power: Option<bool>,
+    pub fan_mode: Option<FanMode>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Ideapad {
+    path: PathBuf,
+}
+
+impl Ideapad {
+    pub fn discover() -> Option<Self> {
+        let path = glob(IDEAPAD_ACPI_GLOB).ok()?.find_map(Result::ok)?;
+        Some(Self { path })
+    }
+
+    fn read(&self, attr: &str) -> Option<u8> {
+        fs::read_to_string(self.path.join(attr))
+            .ok()?
+            .trim()
+            .parse()
+            .ok()
+    }
+
+    fn write(&self, attr: &str, value: u8) -> AResult<()> {
+        if !self.path.join(attr).exists() {
+            Err(aanyhow!("{} is not supported", attr))?
+        }
+        privileged::write_attrs(vec![(self.path.join(attr), value.to_string())])
+    }
+
+    pub fn conservation_mode(&self) -> ConvervationMode {
+        match self.read(CONSERVATION_MODE) {
+            Some(1) => ConvervationMode::Enable,
+            Some(_) => ConvervationMode::Disable,
+            None => ConvervationMode::Unknown,
+        }
+    }
+
+    pub fn extras(&self) -> IdeapadExtras {
+        IdeapadExtras {
+            fn_lock: self.read(FN_LOCK).map(|v| v == 1),
+            camera_power: self.read(CAMERA_POWER).map(|v| v == 1),
+            fan_mode: self.read(FAN_MODE).and_then(FanMode::from_value),
+        }
+    }
+
+    pub fn set_conservation_mode(&self, enable: bool) -> AResult<()> {
+        self.write(CONSERVATION_MODE, enable as u8)
+    }
+
+    pub fn set_fn_lock(&self, enable: bool) -> AResult<()> {
+        self.write(FN_LOCK, enable as u8)
+    }
+
+    pub fn set_camera_power(&self, enable: bool) -> AResult<()> {
+        self.write(CAMERA_POWER, enable as u8)
+    }
+
+    pub fn set_fan_mode(&self, mode: FanMode) -> AResult<()> {
+        self.write(FAN_MODE, mode as u8)
     }
 }
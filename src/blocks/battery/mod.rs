@@ -1,24 +1,42 @@
-use crate::datahodler::channel::DualChannel;
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
 
+use crate::config::{read_config, BatteryBackend};
+use crate::datahodler::channel::{DualChannel, MSender, SSender};
+
+#[cfg(feature = "ideapad")]
+use crate::util::gtk_icon_loader;
 use crate::util::gtk_icon_loader::load_fixed_status_surface;
 use crate::util::timeutil::second_to_human;
+use crate::widgets::battery_popup::BatteryPopup;
 use crate::window::WidgetShareInfo;
 
-use self::common::get_battery_info;
+use self::common::get_power_state;
 #[cfg(feature = "ideapad")]
-use self::ideapad::{get_conservation_mode, ConvervationMode};
+use self::ideapad::{ConvervationMode, FanMode, Ideapad, IdeapadExtras};
 
 use super::Block;
 
 use crate::prelude::*;
-use batdiff::seconds_now;
-use batdiff::BatDiff;
+use alert::Alerts;
+use batdiff::{BatDiff, Remain};
+#[cfg(feature = "ideapad")]
+use chin_tools::aanyhow;
 use chin_tools::AResult;
+use history::History;
+use threshold::{ChargeControl, ChargeLimit};
+use upower::UPowerClient;
 
+mod alert;
 mod batdiff;
 mod common;
+pub mod history;
 #[cfg(feature = "ideapad")]
 mod ideapad;
+mod threshold;
+mod upower;
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum PowerStatus {
@@ -48,6 +66,30 @@ pub struct BatteryInfo {
     pub model_name: String,
     pub manufacturer: String,
     pub serial_numer: String,
+    /// Seconds until empty or full when the backend estimates it, the rate
+    /// is averaged here otherwise.
+    pub remain_secs: Option<usize>,
+    pub warning: WarningLevel,
+}
+
+/// How urgently the battery wants a charger, as told by UPower.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum WarningLevel {
+    None,
+    Low,
+    Critical,
+    /// The system is about to hibernate or power off.
+    Action,
+}
+
+impl WarningLevel {
+    pub fn css_class(&self) -> Option<&'static str> {
+        match self {
+            WarningLevel::None => None,
+            WarningLevel::Low => Some("battery-warning"),
+            WarningLevel::Critical | WarningLevel::Action => Some("battery-critical"),
+        }
+    }
 }
 
 impl BatteryInfo {
@@ -55,33 +97,291 @@ impl BatteryInfo {
         // (self.energy_now * 100 / self.energy_full).try_into().unwrap()
         self.capacity
     }
+
+    /// Instantaneous charge or discharge power, `None` when the battery is
+    /// idle or the driver does not report it.
+    pub fn watts(&self) -> Option<f64> {
+        match self.status {
+            PowerStatus::Charging | PowerStatus::Discharging if self.power_now > 0 => {
+                Some(self.power_now as f64 / 1_000_000.)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Everything read from /sys/class/power_supply in one pass.
+#[derive(Debug, Clone)]
+pub struct PowerState {
+    /// The system batteries combined, `None` without any.
+    pub battery: Option<BatteryInfo>,
+    /// Whether an AC adapter is plugged, `None` when there is no adapter.
+    pub ac_online: Option<bool>,
+    /// Batteries of mice, keyboards, headsets...
+    pub peripherals: Vec<BatteryInfo>,
 }
 
 #[derive(Clone)]
 pub enum BatteryOut {
     #[cfg(feature = "ideapad")]
     ConvervationMode(ConvervationMode),
+    #[cfg(feature = "ideapad")]
+    IdeapadExtras(IdeapadExtras),
     BatteryInfo(BatteryInfo),
     UnknownBatteryInfo,
+    AcOnline(Option<bool>),
+    Peripherals(Vec<BatteryInfo>),
+    ChargeLimit(Option<ChargeLimit>),
 }
 
 #[derive(Clone)]
-pub enum BatteryIn {}
+pub enum BatteryIn {
+    SetChargeLimit(ChargeLimit),
+    #[cfg(feature = "ideapad")]
+    ConservationMode(bool),
+    #[cfg(feature = "ideapad")]
+    FnLock(bool),
+    #[cfg(feature = "ideapad")]
+    CameraPower(bool),
+    #[cfg(feature = "ideapad")]
+    FanMode(FanMode),
+}
 
 pub struct BatteryBlock {
     dualchannel: DualChannel<BatteryOut, BatteryIn>,
-    init_bat_info: BatteryInfo,
+    /// `None` on machines without a battery, the block is hidden then.
+    init_bat_info: Option<BatteryInfo>,
+    charge_control: Option<ChargeControl>,
+    /// Loaded in `run`, shared by the popups of all windows.
+    history: Option<Rc<RefCell<History>>>,
+    /// Set when the `upower` backend is configured and UPower runs.
+    upower: Option<UPowerClient>,
+    #[cfg(feature = "ideapad")]
+    ideapad: Option<Ideapad>,
 }
 
 impl BatteryBlock {
-    pub fn new() -> AResult<Self> {
+    pub fn new() -> Self {
         let dualchannel = DualChannel::new(100);
-        let init_bat_info = get_battery_info()?;
+        let upower = match read_config(|c| c.battery.backend) {
+            BatteryBackend::Sysfs => None,
+            BatteryBackend::Upower => UPowerClient::connect()
+                .inspect_err(|err| log::error!("UPower is unavailable, using sysfs: {}", err))
+                .ok(),
+        };
+        let init_bat_info = match upower.as_ref().map(UPowerClient::power_state) {
+            Some(Ok(state)) => state.battery,
+            _ => get_power_state().battery,
+        };
+        let charge_control = ChargeControl::discover();
 
-        Ok(Self {
+        Self {
             dualchannel,
             init_bat_info,
-        })
+            charge_control,
+            history: None,
+            upower,
+            #[cfg(feature = "ideapad")]
+            ideapad: Ideapad::discover(),
+        }
+    }
+}
+
+fn send_power_state(sender: &MSender<BatteryOut>, state: PowerState) {
+    match state.battery {
+        Some(info) => sender
+            .send(BatteryOut::BatteryInfo(info))
+            .expect("send battery info message"),
+        None => sender
+            .send(BatteryOut::UnknownBatteryInfo)
+            .expect("send battery info message"),
+    };
+    sender
+        .send(BatteryOut::AcOnline(state.ac_online))
+        .expect("send ac message");
+    sender
+        .send(BatteryOut::Peripherals(state.peripherals))
+        .expect("send peripherals message");
+}
+
+fn format_tooltip(
+    battery: &BatteryInfo,
+    ac_online: Option<bool>,
+    charge_limit: Option<ChargeLimit>,
+    peripherals: &[BatteryInfo],
+) -> String {
+    let mut lines = vec![format!(
+        "{}: {}% ({:?})",
+        battery.name,
+        battery.get_percent(),
+        battery.status
+    )];
+    if let Some(watts) = battery.watts() {
+        lines.push(format!("power: {:.2} W", watts));
+    }
+    match ac_online {
+        Some(true) => lines.push("AC: online".to_owned()),
+        Some(false) => lines.push("AC: offline".to_owned()),
+        None => {}
+    }
+    match charge_limit {
+        Some(ChargeLimit::Thresholds {
+            start: Some(start),
+            end,
+        }) if end < 100 => lines.push(format!("charge limit: {}-{}%", start, end)),
+        Some(ChargeLimit::Thresholds { end, .. }) if end < 100 => {
+            lines.push(format!("charge limit: {}%", end))
+        }
+        Some(ChargeLimit::Switch(true)) => lines.push("charge limit: on".to_owned()),
+        Some(_) => lines.push("charge limit: off".to_owned()),
+        None => {}
+    }
+    for peripheral in peripherals {
+        let name = if peripheral.model_name.is_empty() {
+            &peripheral.name
+        } else {
+            &peripheral.model_name
+        };
+        lines.push(format!("{}: {}%", name, peripheral.capacity));
+    }
+
+    lines.join("\n")
+}
+
+fn fill_charge_menu(
+    menu: &gtk::Menu,
+    control: &ChargeControl,
+    current: Option<ChargeLimit>,
+    sender: &SSender<BatteryIn>,
+) {
+    menu.foreach(|child| menu.remove(child));
+
+    let header = gtk::MenuItem::with_label("Charge Limit");
+    header.set_sensitive(false);
+    menu.append(&header);
+
+    if control.has_thresholds() {
+        let current_end = match current {
+            Some(ChargeLimit::Thresholds { end, .. }) => Some(end),
+            _ => None,
+        };
+        for end in read_config(|c| c.battery.charge_limits.clone()) {
+            let label = if end >= 100 {
+                "Full charge".to_owned()
+            } else {
+                format!("Up to {}%", end)
+            };
+            let item = gtk::CheckMenuItem::with_label(&label);
+            item.set_draw_as_radio(true);
+            item.set_active(current_end == Some(end.min(100)));
+            item.connect_activate(clone!(@strong sender => move |_| {
+                let _ = sender.send_blocking(BatteryIn::SetChargeLimit(ChargeLimit::up_to(end)));
+            }));
+            menu.append(&item);
+        }
+    } else {
+        let enabled = current.is_some_and(|limit| limit.is_limited());
+        let item = gtk::CheckMenuItem::with_label("Limit charge");
+        item.set_active(enabled);
+        item.connect_activate(clone!(@strong sender => move |_| {
+            let _ = sender.send_blocking(BatteryIn::SetChargeLimit(ChargeLimit::Switch(!enabled)));
+        }));
+        menu.append(&item);
+    }
+
+    menu.show_all();
+}
+
+#[cfg(feature = "ideapad")]
+fn format_ideapad_tooltip(conservation: ConvervationMode, extras: &IdeapadExtras) -> String {
+    let switch = |on: bool| if on { "on" } else { "off" };
+
+    let mut lines = vec![format!(
+        "Conservation mode: {}",
+        match conservation {
+            ConvervationMode::Enable => "on",
+            ConvervationMode::Disable => "off",
+            ConvervationMode::Unknown => "unknown",
+        }
+    )];
+    if let Some(fn_lock) = extras.fn_lock {
+        lines.push(format!("Fn lock: {}", switch(fn_lock)));
+    }
+    if let Some(camera_power) = extras.camera_power {
+        lines.push(format!("Camera: {}", switch(camera_power)));
+    }
+    if let Some(fan_mode) = extras.fan_mode {
+        lines.push(format!("Fan: {}", fan_mode.name()));
+    }
+
+    lines.join("\n")
+}
+
+#[cfg(feature = "ideapad")]
+fn fill_ideapad_menu(
+    menu: &gtk::Menu,
+    conservation: Option<ConvervationMode>,
+    extras: &IdeapadExtras,
+    sender: &SSender<BatteryIn>,
+) {
+    menu.foreach(|child| menu.remove(child));
+
+    let toggle = |label: &str, active: bool, msg: BatteryIn| {
+        let item = gtk::CheckMenuItem::with_label(label);
+        item.set_active(active);
+        item.connect_activate(clone!(@strong sender => move |_| {
+            let _ = sender.send_blocking(msg.clone());
+        }));
+        menu.append(&item);
+    };
+
+    // `None` when the charge limit menu already switches it.
+    if let Some(conservation) = conservation {
+        let enabled = conservation == ConvervationMode::Enable;
+        toggle(
+            "Conservation mode",
+            enabled,
+            BatteryIn::ConservationMode(!enabled),
+        );
+    }
+    if let Some(fn_lock) = extras.fn_lock {
+        toggle("Fn lock", fn_lock, BatteryIn::FnLock(!fn_lock));
+    }
+    if let Some(camera_power) = extras.camera_power {
+        toggle(
+            "Camera",
+            camera_power,
+            BatteryIn::CameraPower(!camera_power),
+        );
+    }
+
+    if let Some(current) = extras.fan_mode {
+        menu.append(&gtk::SeparatorMenuItem::new());
+        let header = gtk::MenuItem::with_label("Fan Mode");
+        header.set_sensitive(false);
+        menu.append(&header);
+        for mode in FanMode::ALL {
+            let item = gtk::CheckMenuItem::with_label(mode.name());
+            item.set_draw_as_radio(true);
+            item.set_active(mode == current);
+            item.connect_activate(clone!(@strong sender => move |_| {
+                let _ = sender.send_blocking(BatteryIn::FanMode(mode));
+            }));
+            menu.append(&item);
+        }
+    }
+
+    menu.show_all();
+}
+
+#[cfg(feature = "ideapad")]
+fn change_ideapad(ideapad: &Option<Ideapad>, change: impl FnOnce(&Ideapad) -> AResult<()>) {
+    let result = match ideapad {
+        Some(ideapad) => change(ideapad),
+        None => Err(aanyhow!("ideapad_acpi device is not found")),
+    };
+    if let Err(err) = result {
+        log::error!("unable to change ideapad setting: {}", err);
     }
 }
 
@@ -90,31 +390,118 @@ impl Block for BatteryBlock {
     type In = BatteryIn;
 
     fn run(&mut self) -> AResult<()> {
+        let Some(init_bat_info) = self.init_bat_info.as_ref() else {
+            return Ok(());
+        };
+
+        let history = Rc::new(RefCell::new(History::load(read_config(|c| {
+            c.battery.history_days
+        }))));
+        history.borrow_mut().record(init_bat_info);
+        self.history = Some(history.clone());
+
+        // Notifications, their commands and the history follow the battery
+        // once, not once per window.
+        let mut receiver = self.dualchannel.get_out_receiver();
+        MainContext::ref_thread_default().spawn_local(async move {
+            let mut bat_diff = BatDiff::new();
+            let mut alerts = Alerts::new(read_config(|c| c.battery.clone()));
+            loop {
+                if let Ok(msg) = receiver.recv().await {
+                    match msg {
+                        BatteryOut::BatteryInfo(bi) => {
+                            bat_diff.check_remain_time(&bi, |_| {});
+                            alerts.check(&bi, bat_diff.remain());
+                            history.borrow_mut().record(&bi);
+                        }
+                        BatteryOut::AcOnline(online) => alerts.check_ac(online),
+                        _ => {}
+                    }
+                }
+            }
+        });
+
         let sender = self.dualchannel.get_out_sender();
+        let charge_control = self.charge_control.clone();
+        #[cfg(feature = "ideapad")]
+        let ideapad = self.ideapad.clone();
+
+        let mut poll_sysfs = true;
+        if let Some(upower) = self.upower.clone() {
+            let sender = sender.clone();
+            match upower.watch(move |state| send_power_state(&sender, state)) {
+                Ok(_) => poll_sysfs = false,
+                Err(err) => log::error!("unable to follow UPower, using sysfs: {}", err),
+            }
+        }
 
         timeout_add_seconds(
             2,
             clone!(
-                @strong sender =>
+                @strong sender, @strong charge_control =>
                 move || {
-                    match get_battery_info() {
-                        Ok(info) => sender
-                            .send(Self::Out::BatteryInfo(info))
-                            .expect("send battery info message"),
-                        Err(_) => sender
-                            .send(Self::Out::UnknownBatteryInfo)
-                            .expect("send battery info message"),
-                    };
+                    // Before the state, the tooltip is refreshed with it.
+                    sender
+                        .send(Self::Out::ChargeLimit(
+                            charge_control.as_ref().and_then(ChargeControl::read),
+                        ))
+                        .expect("send charge limit message");
+                    if poll_sysfs {
+                        send_power_state(&sender, get_power_state());
+                    }
 
                     #[cfg(feature = "ideapad")]
-                    sender
-                        .send(BatteryOut::ConvervationMode(get_conservation_mode()))
-                        .unwrap();
+                    if let Some(ideapad) = ideapad.as_ref() {
+                        sender
+                            .send(BatteryOut::ConvervationMode(ideapad.conservation_mode()))
+                            .unwrap();
+                        sender
+                            .send(BatteryOut::IdeapadExtras(ideapad.extras()))
+                            .unwrap();
+                    }
                     ControlFlow::Continue
                 }
             ),
         );
 
+        #[cfg(feature = "ideapad")]
+        let ideapad = self.ideapad.clone();
+        let in_receiver = self.dualchannel.get_in_receiver();
+        MainContext::ref_thread_default().spawn_local(async move {
+            loop {
+                if let Ok(msg) = in_receiver.recv().await {
+                    match msg {
+                        BatteryIn::SetChargeLimit(limit) => {
+                            let result = charge_control.as_ref().map(|control| control.set(limit));
+                            match result {
+                                Some(Ok(_)) => {}
+                                Some(Err(err)) => {
+                                    log::error!("unable to set charge limit: {}", err)
+                                }
+                                None => log::error!("charge limit is not supported"),
+                            }
+                        }
+                        #[cfg(feature = "ideapad")]
+                        BatteryIn::ConservationMode(enable) => {
+                            change_ideapad(&ideapad, |i| i.set_conservation_mode(enable))
+                        }
+                        #[cfg(feature = "ideapad")]
+                        BatteryIn::FnLock(enable) => {
+                            change_ideapad(&ideapad, |i| i.set_fn_lock(enable))
+                        }
+                        #[cfg(feature = "ideapad")]
+                        BatteryIn::CameraPower(enable) => {
+                            change_ideapad(&ideapad, |i| i.set_camera_power(enable))
+                        }
+                        #[cfg(feature = "ideapad")]
+                        BatteryIn::FanMode(mode) => {
+                            change_ideapad(&ideapad, |i| i.set_fan_mode(mode))
+                        }
+                    }
+                }
+            }
+        });
+
         Ok(())
     }
 
@@ -123,14 +510,23 @@ impl Block for BatteryBlock {
             .orientation(gtk::Orientation::Horizontal)
             .build();
 
+        let (Some(init_bat_info), Some(history)) =
+            (self.init_bat_info.as_ref(), self.history.clone())
+        else {
+            holder.set_no_show_all(true);
+            return holder.upcast();
+        };
+
         let percent_icon = gtk::Image::new();
         let power_status_icon = gtk::Image::new();
         let percent_label = gtk::Label::builder().build();
         let remain_time_label = gtk::Label::builder().build();
+        let power_label = gtk::Label::builder().build();
 
         percent_icon.style_context().add_class("f-20");
         percent_label.style_context().add_class("battery-label");
         remain_time_label.style_context().add_class("battery-label");
+        power_label.style_context().add_class("battery-label");
 
         #[cfg(feature = "ideapad")]
         let convervation_icon =
@@ -139,33 +535,92 @@ impl Block for BatteryBlock {
         holder.pack_start(&percent_icon, false, false, 0);
         holder.pack_start(&power_status_icon, false, false, 0);
         #[cfg(feature = "ideapad")]
-        holder.pack_start(&convervation_icon, false, false, 0);
+        let convervation_box = EventBox::builder().child(&convervation_icon).build();
+        #[cfg(feature = "ideapad")]
+        holder.pack_start(&convervation_box, false, false, 0);
         holder.pack_start(&percent_label, false, false, 0);
         holder.pack_start(&remain_time_label, false, false, 0);
+        holder.pack_start(&power_label, false, false, 0);
 
         #[cfg(feature = "ideapad")]
-        let mut cm_status = ConvervationMode::Unknown;
-
-        let mut bat_diff = BatDiff {
-            last_power_status: PowerStatus::Unknown,
-            last_percent: 0,
-            energy_diff: 0,
-            time_diff: 0,
-            last_record_seconds: seconds_now(),
-            last_record_energy: self.init_bat_info.energy_now as usize,
-            last_remain_time_notify_sec: 0,
-            last_remain_time_label_time: seconds_now(),
-        };
+        let cm_status = Rc::new(Cell::new(ConvervationMode::Unknown));
+        #[cfg(feature = "ideapad")]
+        let ideapad_extras: Rc<RefCell<IdeapadExtras>> = Default::default();
+        #[cfg(feature = "ideapad")]
+        if self.ideapad.is_some() {
+            // Left click toggles conservation mode, right click lists the
+            // other switches of the laptop.
+            let menu = gtk::Menu::new();
+            menu.set_attach_widget(Some(&convervation_box));
+            let sender = self.dualchannel.in_sender.clone();
+            let in_charge_menu = self
+                .charge_control
+                .as_ref()
+                .is_some_and(ChargeControl::is_conservation_mode);
+            convervation_box.connect_button_release_event(
+                clone!(@strong cm_status, @strong ideapad_extras => move |_, v1| match v1.button() {
+                    1 => {
+                        let enabled = cm_status.get() == ConvervationMode::Enable;
+                        let _ = sender.send_blocking(BatteryIn::ConservationMode(!enabled));
+                        Propagation::Stop
+                    }
+                    3 => {
+                        let conservation = (!in_charge_menu).then(|| cm_status.get());
+                        fill_ideapad_menu(&menu, conservation, &ideapad_extras.borrow(), &sender);
+                        menu.popup_at_pointer(Some(v1));
+                        Propagation::Stop
+                    }
+                    _ => Propagation::Proceed,
+                }),
+            );
+        } else {
+            convervation_box.set_no_show_all(true);
+        }
+
+        let mut bat_diff = BatDiff::new();
 
-        bat_diff.check_percent(&self.init_bat_info, |percent, mapped| {
+        bat_diff.check_percent(init_bat_info, |percent, mapped| {
             percent_label.set_label(&format!("{}%", percent));
             percent_icon.set_from_surface(load_fixed_status_surface(mapped).as_ref());
         });
 
-        bat_diff.check_power_status(&self.init_bat_info, |mapped| {
+        bat_diff.check_power_status(init_bat_info, |mapped| {
             power_status_icon.set_from_surface(load_fixed_status_surface(mapped).as_ref());
         });
 
+        let mut battery = init_bat_info.clone();
+        let mut ac_online = None;
+        let charge_limit: Rc<Cell<Option<ChargeLimit>>> = Default::default();
+
+        let holder = EventBox::builder().child(&holder).build();
+        holder.set_tooltip_text(Some(&format_tooltip(&battery, ac_online, None, &[])));
+
+        let popup = BatteryPopup::new(&holder, history);
+        popup.update(&battery);
+
+        let menu = gtk::Menu::new();
+        menu.set_attach_widget(Some(&holder));
+        let control = self.charge_control.clone();
+        let sender = self.dualchannel.in_sender.clone();
+        holder.connect_button_release_event(
+            clone!(@strong charge_limit, @strong popup => move |_, v1| match v1.button() {
+                1 => {
+                    popup.toggle();
+                    Propagation::Stop
+                }
+                3 => match control.as_ref() {
+                    Some(control) => {
+                        fill_charge_menu(&menu, control, charge_limit.get(), &sender);
+                        menu.popup_at_pointer(Some(v1));
+                        Propagation::Stop
+                    }
+                    None => Propagation::Proceed,
+                },
+                _ => Propagation::Proceed,
+            }),
+        );
+
+        let block = holder.clone();
         let mut receiver = self.dualchannel.get_out_receiver();
         MainContext::ref_thread_default().spawn_local(async move {
             loop {
@@ -173,9 +628,9 @@ impl Block for BatteryBlock {
                     match msg {
                         #[cfg(feature = "ideapad")]
                         BatteryOut::ConvervationMode(cm) => {
-                            if cm_status != cm {
-                                cm_status = cm;
-                                let mapped = match cm_status {
+                            if cm_status.get() != cm {
+                                cm_status.set(cm);
+                                let mapped = match cm {
                                     ConvervationMode::Enable => StatusName::BatteryConservationOn,
                                     ConvervationMode::Disable => StatusName::BatteryConservationOff,
                                     ConvervationMode::Unknown => {
@@ -186,6 +641,14 @@ impl Block for BatteryBlock {
                                     .set_from_surface(load_fixed_status_surface(mapped).as_ref())
                             }
                         }
+                        #[cfg(feature = "ideapad")]
+                        BatteryOut::IdeapadExtras(extras) => {
+                            convervation_box.set_tooltip_text(Some(&format_ideapad_tooltip(
+                                cm_status.get(),
+                                &extras,
+                            )));
+                            ideapad_extras.replace(extras);
+                        }
                         BatteryOut::BatteryInfo(bi) => {
                             bat_diff.check_percent(&bi, |percent, mapped| {
                                 tracing::info!("set battery");
@@ -199,16 +662,46 @@ impl Block for BatteryBlock {
                                     .set_from_surface(load_fixed_status_surface(mapped).as_ref());
                             });
 
-                            bat_diff.check_remain_time(&bi, |mapped| {
-                                if let Some(time) = mapped {
-                                    remain_time_label
-                                        .set_label(&format!("({})", second_to_human(time)));
-                                } else {
-                                    remain_time_label.set_label("");
-                                }
+                            bat_diff.check_remain_time(&bi, |mapped| match mapped {
+                                Some(Remain::Empty(time)) => remain_time_label
+                                    .set_label(&format!("({})", second_to_human(time))),
+                                Some(Remain::Full(time)) => remain_time_label
+                                    .set_label(&format!("({} to full)", second_to_human(time))),
+                                None => remain_time_label.set_label(""),
                             });
+
+                            let style = block.style_context();
+                            for class in [WarningLevel::Low, WarningLevel::Critical]
+                                .iter()
+                                .filter_map(WarningLevel::css_class)
+                            {
+                                style.remove_class(class);
+                            }
+                            if let Some(class) = bi.warning.css_class() {
+                                style.add_class(class);
+                            }
+
+                            match bi.watts() {
+                                Some(watts) => power_label.set_label(&format!("{:.1}W", watts)),
+                                None => power_label.set_label(""),
+                            }
+
+                            popup.update(&bi);
+
+                            battery = bi;
                         }
                         BatteryOut::UnknownBatteryInfo => {}
+                        BatteryOut::AcOnline(online) => ac_online = online,
+                        BatteryOut::ChargeLimit(limit) => charge_limit.set(limit),
+                        BatteryOut::Peripherals(peripherals) => {
+                            // Sent last in every refresh.
+                            block.set_tooltip_text(Some(&format_tooltip(
+                                &battery,
+                                ac_online,
+                                charge_limit.get(),
+                                &peripherals,
+                            )));
+                        }
                     }
                 }
             }
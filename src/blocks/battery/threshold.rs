@@ -0,0 +1,183 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use chin_tools::{aanyhow, AResult};
+use glob::glob;
+
+use crate::util::privileged;
+
+use super::common::{power_supplies, SupplyType};
+
+const START_THRESHOLD: &str = "charge_control_start_threshold";
+const END_THRESHOLD: &str = "charge_control_end_threshold";
+// "start end" in one attribute.
+const HUAWEI_THRESHOLDS: &str = "/sys/devices/platform/huawei-wmi/charge_control_thresholds";
+const IDEAPAD_CONSERVATION_GLOB: &str =
+    "/sys/bus/platform/drivers/ideapad_acpi/*/conservation_mode";
+const SAMSUNG_LIFE_EXTENDER: &str = "/sys/devices/platform/samsung/battery_life_extender";
+
+/// How far below the end threshold charging starts again, so the battery
+/// is not topped up for every percent it loses.
+const START_GAP: u8 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChargeLimit {
+    /// Charging starts below `start` and stops at `end` percent.
+    Thresholds { start: Option<u8>, end: u8 },
+    /// A vendor switch which caps the charge at a level chosen by the
+    /// firmware.
+    Switch(bool),
+}
+
+impl ChargeLimit {
+    /// Thresholds stopping at `end`, starting again a little below it.
+    pub fn up_to(end: u8) -> Self {
+        let start = if end >= 100 {
+            0
+        } else {
+            end.saturating_sub(START_GAP)
+        };
+        ChargeLimit::Thresholds {
+            start: Some(start),
+            end: end.min(100),
+        }
+    }
+
+    pub fn is_limited(&self) -> bool {
+        match self {
+            ChargeLimit::Thresholds { end, .. } => *end < 100,
+            ChargeLimit::Switch(enabled) => *enabled,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Knob {
+    /// The power_supply class attributes, on every battery of the system.
+    Generic(Vec<PathBuf>),
+    Huawei,
+    Switch(PathBuf),
+}
+
+/// The charge limit interface of this machine.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChargeControl {
+    knob: Knob,
+}
+
+impl ChargeControl {
+    pub fn discover() -> Option<Self> {
+        let batteries: Vec<PathBuf> = power_supplies()
+            .into_iter()
+            .filter(|s| s.kind == SupplyType::Battery && !s.peripheral)
+            .map(|s| s.path)
+            .filter(|p| p.join(END_THRESHOLD).exists())
+            .collect();
+        let knob = if !batteries.is_empty() {
+            Knob::Generic(batteries)
+        } else if Path::new(HUAWEI_THRESHOLDS).exists() {
+            Knob::Huawei
+        } else if let Some(path) = glob(IDEAPAD_CONSERVATION_GLOB)
+            .ok()
+            .and_then(|mut paths| paths.find_map(Result::ok))
+        {
+            Knob::Switch(path)
+        } else if Path::new(SAMSUNG_LIFE_EXTENDER).exists() {
+            Knob::Switch(SAMSUNG_LIFE_EXTENDER.into())
+        } else {
+            return None;
+        };
+
+        Some(Self { knob })
+    }
+
+    /// Whether limits are set with [`ChargeLimit::Thresholds`] rather than
+    /// a switch.
+    pub fn has_thresholds(&self) -> bool {
+        !matches!(self.knob, Knob::Switch(_))
+    }
+
+    /// Whether the limit is the conservation mode of ideapad_acpi.
+    pub fn is_conservation_mode(&self) -> bool {
+        matches!(&self.knob, Knob::Switch(path) if path.ends_with("conservation_mode"))
+    }
+
+    pub fn read(&self) -> Option<ChargeLimit> {
+        let number =
+            |path: &Path| -> Option<u8> { fs::read_to_string(path).ok()?.trim().parse().ok() };
+
+        match &self.knob {
+            Knob::Generic(batteries) => {
+                // Packs are written together, the first one speaks for all.
+                let battery = batteries.first()?;
+                Some(ChargeLimit::Thresholds {
+                    start: number(&battery.join(START_THRESHOLD)),
+                    end: number(&battery.join(END_THRESHOLD))?,
+                })
+            }
+            Knob::Huawei => {
+                let content = fs::read_to_string(HUAWEI_THRESHOLDS).ok()?;
+                let (start, end) = content.trim().split_once(' ')?;
+                Some(ChargeLimit::Thresholds {
+                    start: start.trim().parse().ok(),
+                    // 0 0 is the firmware default of charging fully.
+                    end: match end.trim().parse().ok()? {
+                        0 => 100,
+                        end => end,
+                    },
+                })
+            }
+            Knob::Switch(path) => Some(ChargeLimit::Switch(number(path)? == 1)),
+        }
+    }
+
+    pub fn set(&self, limit: ChargeLimit) -> AResult<()> {
+        let attrs = match (&self.knob, limit) {
+            (Knob::Generic(batteries), ChargeLimit::Thresholds { start, end }) => {
+                // The kernel refuses a start above the current end or an end
+                // below the current start, so order the writes to move away
+                // from the current values.
+                let raising = match self.read() {
+                    Some(ChargeLimit::Thresholds { end: current, .. }) => end >= current,
+                    _ => true,
+                };
+                let mut attrs = vec![];
+                for battery in batteries {
+                    let end = (battery.join(END_THRESHOLD), end.to_string());
+                    let start = start
+                        .filter(|_| battery.join(START_THRESHOLD).exists())
+                        .map(|start| (battery.join(START_THRESHOLD), start.to_string()));
+                    if raising {
+                        attrs.push(end);
+                        attrs.extend(start);
+                    } else {
+                        attrs.extend(start);
+                        attrs.push(end);
+                    }
+                }
+                attrs
+            }
+            (Knob::Huawei, ChargeLimit::Thresholds { start, end }) => {
+                let value = if end >= 100 {
+                    "0 0".to_owned()
+                } else {
+                    format!("{} {}", start.unwrap_or_default(), end)
+                };
+                vec![(HUAWEI_THRESHOLDS.into(), value)]
+            }
+            (Knob::Switch(path), ChargeLimit::Switch(enabled)) => {
+                vec![(path.clone(), if enabled { "1" } else { "0" }.to_owned())]
+            }
+            // A percentage on a switch means limited or not.
+            (Knob::Switch(path), limit) => vec![(
+                path.clone(),
+                if limit.is_limited() { "1" } else { "0" }.to_owned(),
+            )],
+            (_, ChargeLimit::Switch(_)) => Err(aanyhow!("charge limit is set with thresholds"))?,
+        };
+
+        privileged::write_attrs(attrs)
+    }
+}
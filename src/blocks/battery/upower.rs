@@ -0,0 +1,253 @@
+use std::{collections::HashMap, iter, sync::mpsc, thread};
+
+use zbus::{
+    blocking::{fdo::PropertiesProxy, Connection, MessageIterator},
+    message,
+    names::InterfaceName,
+    proxy,
+    zvariant::{OwnedObjectPath, OwnedValue},
+    MatchRule,
+};
+
+use super::common::combine;
+use super::{BatteryInfo, PowerState, PowerStatus, WarningLevel};
+
+const UPOWER: &str = "org.freedesktop.UPower";
+const UPOWER_PATH: &str = "/org/freedesktop/UPower";
+const DEVICE_INTERFACE: &str = "org.freedesktop.UPower.Device";
+
+// Values of the Type property.
+const TYPE_UNKNOWN: u32 = 0;
+const TYPE_LINE_POWER: u32 = 1;
+const TYPE_BATTERY: u32 = 2;
+
+#[proxy(
+    interface = "org.freedesktop.UPower",
+    default_service = "org.freedesktop.UPower",
+    default_path = "/org/freedesktop/UPower"
+)]
+trait UPower {
+    fn enumerate_devices(&self) -> zbus::Result<Vec<OwnedObjectPath>>;
+
+    fn get_display_device(&self) -> zbus::Result<OwnedObjectPath>;
+}
+
+type Properties = HashMap<String, OwnedValue>;
+
+fn prop<T: TryFrom<OwnedValue>>(props: &Properties, name: &str) -> Option<T> {
+    T::try_from(props.get(name)?.try_clone().ok()?).ok()
+}
+
+fn text(props: &Properties, name: &str) -> String {
+    props
+        .get(name)
+        .and_then(|v| v.downcast_ref::<&str>().ok())
+        .unwrap_or_default()
+        .to_owned()
+}
+
+/// A device as [`BatteryInfo`], in the units of the power_supply class.
+fn battery_info(props: &Properties) -> BatteryInfo {
+    let micro = |name: &str| (prop::<f64>(props, name).unwrap_or_default() * 1_000_000.) as u32;
+
+    let status = match prop::<u32>(props, "State") {
+        Some(1) => PowerStatus::Charging,
+        Some(2) | Some(3) => PowerStatus::Discharging,
+        Some(4) => PowerStatus::Full,
+        Some(5) | Some(6) => PowerStatus::NotCharging,
+        _ => PowerStatus::Unknown,
+    };
+    let warning = match prop::<u32>(props, "WarningLevel") {
+        // 2 is a discharging UPS.
+        Some(2) | Some(3) => WarningLevel::Low,
+        Some(4) => WarningLevel::Critical,
+        Some(5) => WarningLevel::Action,
+        _ => WarningLevel::None,
+    };
+    let technology = match prop::<u32>(props, "Technology") {
+        Some(1) => "Li-ion",
+        Some(2) => "Li-poly",
+        Some(3) => "LiFe",
+        Some(4) => "Lead-acid",
+        Some(5) => "NiCd",
+        Some(6) => "NiMH",
+        _ => "Unknown",
+    };
+    let remain_secs = match status {
+        PowerStatus::Discharging => prop::<i64>(props, "TimeToEmpty"),
+        PowerStatus::Charging => prop::<i64>(props, "TimeToFull"),
+        _ => None,
+    };
+    let native_path = text(props, "NativePath");
+    let name = native_path
+        .rsplit('/')
+        .next()
+        .unwrap_or_default()
+        .to_owned();
+
+    BatteryInfo {
+        name,
+        status,
+        present: prop::<bool>(props, "IsPresent").unwrap_or(true) as u8,
+        technology: technology.to_owned(),
+        cycle_count: prop::<i32>(props, "ChargeCycles")
+            .unwrap_or_default()
+            .max(0) as u32,
+        voltage_min_design: 0,
+        voltage_now: micro("Voltage"),
+        power_now: micro("EnergyRate"),
+        energy_full_design: micro("EnergyFullDesign"),
+        energy_full: micro("EnergyFull"),
+        energy_now: micro("Energy"),
+        capacity: prop::<f64>(props, "Percentage")
+            .unwrap_or_default()
+            .round()
+            .clamp(0., 100.) as u8,
+        capacity_level: match warning {
+            WarningLevel::None => "Normal",
+            WarningLevel::Low => "Low",
+            WarningLevel::Critical | WarningLevel::Action => "Critical",
+        }
+        .to_owned(),
+        model_name: text(props, "Model"),
+        manufacturer: text(props, "Vendor"),
+        serial_numer: text(props, "Serial"),
+        // 0 while UPower has no estimate yet.
+        remain_secs: remain_secs
+            .filter(|secs| *secs > 0)
+            .map(|secs| secs as usize),
+        warning,
+    }
+}
+
+/// The battery state as UPower sees it.
+#[derive(Clone)]
+pub struct UPowerClient {
+    conn: Connection,
+    proxy: UPowerProxyBlocking<'static>,
+    display_device: OwnedObjectPath,
+}
+
+impl UPowerClient {
+    pub fn connect() -> zbus::Result<Self> {
+        let conn = Connection::system()?;
+        let proxy = UPowerProxyBlocking::new(&conn)?;
+        let display_device = proxy.get_display_device()?;
+
+        Ok(Self {
+            conn,
+            proxy,
+            display_device,
+        })
+    }
+
+    fn properties(&self, path: &OwnedObjectPath) -> zbus::Result<Properties> {
+        let proxy = PropertiesProxy::builder(&self.conn)
+            .destination(UPOWER)?
+            .path(path)?
+            .build()?;
+        Ok(proxy.get_all(InterfaceName::from_static_str_unchecked(DEVICE_INTERFACE))?)
+    }
+
+    pub fn power_state(&self) -> zbus::Result<PowerState> {
+        let mut batteries = vec![];
+        let mut peripherals = vec![];
+        let mut ac_online = None;
+
+        for path in self.proxy.enumerate_devices()? {
+            let props = match self.properties(&path) {
+                Ok(props) => props,
+                // Unplugged while we were enumerating.
+                Err(err) => {
+                    log::debug!("unable to read {}: {}", path.as_str(), err);
+                    continue;
+                }
+            };
+            let kind = prop::<u32>(&props, "Type").unwrap_or(TYPE_UNKNOWN);
+            let power_supply = prop::<bool>(&props, "PowerSupply").unwrap_or_default();
+            match kind {
+                TYPE_UNKNOWN => {}
+                TYPE_LINE_POWER => {
+                    let online = prop::<bool>(&props, "Online").unwrap_or_default();
+                    ac_online = Some(ac_online.unwrap_or_default() || online);
+                }
+                TYPE_BATTERY if power_supply => {
+                    let info = battery_info(&props);
+                    if info.present == 1 {
+                        batteries.push(info);
+                    }
+                }
+                _ => peripherals.push(battery_info(&props)),
+            }
+        }
+
+        // Identity, health and cycles come from the packs, the charge and
+        // the estimates from the display device which already combines
+        // them.
+        let mut battery = combine(batteries);
+        if let Some(battery) = battery.as_mut() {
+            let display = battery_info(&self.properties(&self.display_device)?);
+            battery.status = display.status;
+            battery.capacity = display.capacity;
+            battery.energy_now = display.energy_now;
+            battery.energy_full = display.energy_full;
+            battery.power_now = display.power_now;
+            battery.remain_secs = display.remain_secs;
+            battery.warning = display.warning;
+        }
+
+        Ok(PowerState {
+            battery,
+            ac_online,
+            peripherals,
+        })
+    }
+
+    /// Call `callback` with the current state and again on every change
+    /// UPower signals, from a thread of its own.
+    pub fn watch(self, callback: impl Fn(PowerState) + Send + 'static) -> zbus::Result<()> {
+        // Property changes of every device and devices coming and going.
+        let rule = MatchRule::builder()
+            .msg_type(message::Type::Signal)
+            .sender(UPOWER)?
+            .path_namespace(UPOWER_PATH)?
+            .build();
+        let messages = MessageIterator::for_match_rule(rule, &self.conn, Some(64))?;
+
+        // Forwarded so the signals already queued can be taken without
+        // blocking.
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            for msg in messages {
+                if tx.send(msg).is_err() {
+                    break;
+                }
+            }
+        });
+
+        thread::spawn(move || {
+            let update = || match self.power_state() {
+                Ok(state) => callback(state),
+                Err(err) => log::error!("unable to read the battery from UPower: {}", err),
+            };
+
+            update();
+            while let Ok(msg) = rx.recv() {
+                // A refresh changes several properties of several devices
+                // at once, read the state once for the whole burst.
+                let mut changed = false;
+                for msg in iter::once(msg).chain(rx.try_iter()) {
+                    match msg {
+                        Ok(_) => changed = true,
+                        Err(err) => log::error!("UPower signal error: {}", err),
+                    }
+                }
+                if changed {
+                    update();
+                }
+            }
+        });
+
+        Ok(())
+    }
+}
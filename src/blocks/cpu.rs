@@ -1,22 +1,57 @@
-use std::{fs, str::FromStr, time::Duration};
+use std::{
+    cell::RefCell,
+    fs,
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::Duration,
+};
 
 use chin_tools::AResult;
+use glob::glob;
 
+use crate::config::{read_config, FrequencyDisplay};
+use crate::datahodler::channel::SSender;
 use crate::prelude::*;
-use crate::util::gtk_icon_loader::StatusName;
+use crate::util::gtk_icon_loader::{load_fixed_status_surface, StatusName};
+use crate::{util::gtk_icon_loader, window::WidgetShareInfo};
 use crate::{
-    util::fileutil,
-    widgets::chart::{Chart, Column},
+    util::{
+        fileutil::ProcFile,
+        privileged,
+        procparse::{self, CpuTime},
+    },
+    widgets::{
+        chart::{Chart, Column},
+        process_popup::ProcessPopup,
+    },
 };
-use crate::{util::gtk_icon_loader, window::WidgetShareInfo};
 
-use super::{temp, Block};
+use super::{
+    hwmon::{self, SensorKind},
+    process::ProcessSort,
+    temp, Block,
+};
 
 const CPU_BOOST_PATH: &str = "/sys/devices/system/cpu/cpufreq/boost";
 const CPU_NO_TURBO_PATH: &str = "/sys/devices/system/cpu/intel_pstate/no_turbo";
+const CPUFREQ_POLICY_GLOB: &str = "/sys/devices/system/cpu/cpufreq/policy*";
+const PROC_STAT: &str = "/proc/stat";
 
 #[derive(Clone)]
-pub enum CpuIn {}
+pub enum CpuIn {
+    ToggleBoost,
+    SetGovernor(String),
+    SetEnergyPreference(String),
+}
+
+/// Current and available cpufreq scaling settings, read from the first policy.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ScalingInfo {
+    pub governor: Option<String>,
+    pub governors: Vec<String>,
+    pub energy_preference: Option<String>,
+    pub energy_preferences: Vec<String>,
+}
 
 #[derive(Clone)]
 pub enum CpuOut {
@@ -24,17 +59,23 @@ pub enum CpuOut {
     UtilizationAvg(f64, f64),
     Utilizations(Vec<f64>),
     CpuTemp(f64),
+    Boost(Option<bool>),
+    Scaling(ScalingInfo),
 }
 
 pub struct CpuBlock {
     dualchannel: DualChannel<CpuOut, CpuIn>,
+    policies: Vec<PathBuf>,
 }
 
 impl CpuBlock {
     pub fn new() -> Self {
         let dualchannel = DualChannel::new(30);
 
-        CpuBlock { dualchannel }
+        CpuBlock {
+            dualchannel,
+            policies: cpufreq_policies(),
+        }
     }
 }
 
@@ -44,52 +85,96 @@ impl Block for CpuBlock {
     type In = CpuIn;
 
     fn run(&mut self) -> AResult<()> {
-        let mut cputime = read_proc_stat()?;
-        let cores = cputime.1.len();
+        let mut stat = ProcFile::open(PROC_STAT)?;
+        let mut last_cores = Vec::with_capacity(32);
+        let mut last_total = read_proc_stat(&mut stat, &mut last_cores)?;
+        let cores = last_cores.len();
 
         if cores == 0 {
             Err(aanyhow!("/proc/stat reported zero cores"))?
         }
 
-        let temp_file = temp::match_type_dir("x86_pkg_temp").map(|mut p| {
-            p.push("temp");
-            p
-        });
+        let mut temp_file = cpu_temp_file().and_then(|path| Ok(ProcFile::open(path)?));
 
+        let mut freq_files = open_frequencies(&self.policies);
+        let mut new_cores = Vec::with_capacity(cores);
         let sender = self.dualchannel.get_out_sender();
         timeout_add_seconds_local(1, move || {
-            let freqs = read_frequencies().expect("unable to read frequencies");
-            sender.send(CpuOut::Frequencies(freqs)).unwrap();
+            if let Ok(freqs) = read_frequencies(&mut freq_files) {
+                sender.send(CpuOut::Frequencies(freqs)).unwrap();
+            }
 
             // Compute utilizations
-            let new_cputime = read_proc_stat().unwrap();
-            let utilization_avg = new_cputime.0.utilization_user_and_system(cputime.0);
+            let Ok(total) = read_proc_stat(&mut stat, &mut new_cores) else {
+                return ControlFlow::Continue;
+            };
+            let utilization_avg = total.utilization_user_and_system(last_total);
             sender
                 .send(CpuOut::UtilizationAvg(utilization_avg.0, utilization_avg.1))
                 .unwrap();
-            let mut utilizations = Vec::new();
-            for i in 0..cores {
-                utilizations.push(new_cputime.1[i].utilization(cputime.1[i]));
-            }
+            let utilizations = new_cores
+                .iter()
+                .zip(last_cores.iter())
+                .map(|(new, old)| new.utilization(*old))
+                .collect();
             sender.send(CpuOut::Utilizations(utilizations)).unwrap();
 
-            cputime = new_cputime;
+            last_total = total;
+            std::mem::swap(&mut last_cores, &mut new_cores);
 
             ControlFlow::Continue
         });
 
+        let policies = self.policies.clone();
         let sender = self.dualchannel.get_out_sender();
         timeout_add_local(Duration::from_millis(1600), move || {
-            if let Ok(temp_path) = temp_file.as_ref() {
-                let temp = temp::read_type_temp(temp_path);
+            if let Ok(file) = temp_file.as_mut() {
+                let temp = temp::read_type_temp(file);
                 if let Ok(temp) = temp {
                     sender.send(CpuOut::CpuTemp(temp)).unwrap();
                 }
             };
 
+            sender.send(CpuOut::Boost(boost_status())).unwrap();
+            sender
+                .send(CpuOut::Scaling(read_scaling_info(&policies)))
+                .unwrap();
+
             ControlFlow::Continue
         });
 
+        let policies = self.policies.clone();
+        let in_receiver = self.dualchannel.get_in_receiver();
+        MainContext::ref_thread_default().spawn_local(async move {
+            loop {
+                if let Ok(msg) = in_receiver.recv().await {
+                    match msg {
+                        CpuIn::ToggleBoost => {
+                            if let Some(boost) = boost_status() {
+                                if let Err(err) = set_boost(!boost) {
+                                    log::error!("unable to toggle cpu boost: {}", err);
+                                }
+                            }
+                        }
+                        CpuIn::SetGovernor(governor) => {
+                            if let Err(err) =
+                                set_policy_attr(&policies, "scaling_governor", &governor)
+                            {
+                                log::error!("unable to set cpu governor: {}", err);
+                            }
+                        }
+                        CpuIn::SetEnergyPreference(epp) => {
+                            if let Err(err) =
+                                set_policy_attr(&policies, "energy_performance_preference", &epp)
+                            {
+                                log::error!("unable to set energy performance preference: {}", err);
+                            }
+                        }
+                    }
+                }
+            }
+        });
+
         Ok(())
     }
 
@@ -118,11 +203,15 @@ impl Block for CpuBlock {
         utilization_label.style_context().add_class("cpu-util");
         let temp_label = gtk::Label::builder().build();
         temp_label.style_context().add_class("cpu-temp");
+        let freq_label = gtk::Label::builder().build();
+        freq_label.style_context().add_class("cpu-freq");
 
         label_holder.pack_start(&utilization_label, false, false, 0);
 
         label_holder.pack_start(&temp_label, false, false, 0);
 
+        label_holder.pack_start(&freq_label, false, false, 0);
+
         right_holder.pack_start(&label_holder, false, false, 0);
 
         let user_column = Column::new("cpu_user", 100., 50, RGBA::new(0.4, 0.2, 0.2, 0.6));
@@ -137,14 +226,73 @@ impl Block for CpuBlock {
 
         right_holder.pack_end(&chart.drawing_box, true, true, 0);
 
+        let boost_icon = gtk_icon_loader::load_fixed_status_image(StatusName::CpuBoostOff);
+        let boost_holder = EventBox::builder().child(&boost_icon).build();
+
+        let sender = self.dualchannel.in_sender.clone();
+        boost_holder.connect_button_release_event(move |_, v1| match v1.button() {
+            1 => {
+                let _ = sender.send_blocking(CpuIn::ToggleBoost);
+                Propagation::Stop
+            }
+            _ => Propagation::Proceed,
+        });
+
         holder.pack_start(&icon, false, false, 0);
+        holder.pack_start(&boost_holder, false, false, 0);
         holder.pack_end(&right_holder, false, false, 0);
 
+        let holder = EventBox::builder().child(&holder).build();
+
+        let scaling: Rc<RefCell<ScalingInfo>> = Default::default();
+        let menu = gtk::Menu::new();
+        menu.set_attach_widget(Some(&holder));
+        let processes =
+            ProcessPopup::new(&holder, ProcessSort::Cpu, read_config(|c| c.top_processes));
+        let sender = self.dualchannel.in_sender.clone();
+        holder.connect_button_release_event(
+            clone!(@strong scaling => move |_, v1| match v1.button() {
+                1 => {
+                    processes.toggle();
+                    Propagation::Stop
+                }
+                3 => {
+                    fill_scaling_menu(&menu, &scaling.borrow(), &sender);
+                    if !menu.children().is_empty() {
+                        menu.popup_at_pointer(Some(v1));
+                    }
+                    Propagation::Stop
+                }
+                _ => Propagation::Proceed,
+            }),
+        );
+
+        let frequency_display = read_config(|c| c.cpu.frequency);
+        let mut last_boost = None;
+        let block = holder.clone();
+
         MainContext::ref_thread_default().spawn_local(async move {
             loop {
                 if let Ok(msg) = receiver.recv().await {
                     match msg {
-                        CpuOut::Frequencies(_) => {}
+                        CpuOut::Frequencies(freqs) => {
+                            if freqs.is_empty() {
+                                freq_label.set_label("");
+                                continue;
+                            }
+
+                            let max = freqs.iter().cloned().fold(0., f64::max);
+                            let avg = freqs.iter().sum::<f64>() / freqs.len() as f64;
+                            let shown = match frequency_display {
+                                FrequencyDisplay::Avg => avg,
+                                FrequencyDisplay::Max => max,
+                            };
+                            freq_label.set_label(format!("{:.1}G", shown / 1e9).as_str());
+                            freq_label.set_tooltip_text(Some(
+                                format!("avg {:.2} GHz\nmax {:.2} GHz", avg / 1e9, max / 1e9)
+                                    .as_str(),
+                            ));
+                        }
                         CpuOut::UtilizationAvg(user, system) => {
                             system_column.add_value(system * 100.);
                             user_column.add_value(user * 100.);
@@ -155,6 +303,52 @@ impl Block for CpuBlock {
                         CpuOut::CpuTemp(temp) => {
                             temp_label.set_label(format!("{:.1}C", temp).as_str())
                         }
+                        CpuOut::Boost(boost) => {
+                            boost_holder.set_visible(boost.is_some());
+                            if last_boost != boost {
+                                let mapped = if boost == Some(true) {
+                                    StatusName::CpuBoostOn
+                                } else {
+                                    StatusName::CpuBoostOff
+                                };
+                                boost_icon
+                                    .set_from_surface(load_fixed_status_surface(mapped).as_ref());
+                                last_boost = boost;
+                            }
+                        }
+                        CpuOut::Scaling(info) => {
+                            if *scaling.borrow() == info {
+                                continue;
+                            }
+
+                            let old = scaling.replace(info);
+                            let new = scaling.borrow();
+                            let style = block.style_context();
+                            if let Some(governor) = old.governor.as_ref() {
+                                style.remove_class(&format!("cpu-governor-{}", governor));
+                            }
+                            if let Some(epp) = old.energy_preference.as_ref() {
+                                style.remove_class(&format!("cpu-epp-{}", epp));
+                            }
+                            if let Some(governor) = new.governor.as_ref() {
+                                style.add_class(&format!("cpu-governor-{}", governor));
+                            }
+                            if let Some(epp) = new.energy_preference.as_ref() {
+                                style.add_class(&format!("cpu-epp-{}", epp));
+                            }
+
+                            let tooltip = [
+                                new.governor.as_ref().map(|g| format!("governor: {}", g)),
+                                new.energy_preference
+                                    .as_ref()
+                                    .map(|e| format!("epp: {}", e)),
+                            ]
+                            .into_iter()
+                            .flatten()
+                            .collect::<Vec<String>>()
+                            .join("\n");
+                            icon.set_tooltip_text(Some(tooltip.as_str()).filter(|t| !t.is_empty()));
+                        }
                     }
                 }
             }
@@ -164,101 +358,52 @@ impl Block for CpuBlock {
     }
 }
 
-// Read frequencies (read in MHz, store in Hz)
-fn read_frequencies() -> AResult<Vec<f64>> {
-    let freqs: Vec<f64> = fileutil::read_lines("/proc/cpuinfo")
-        .expect("Unable to read /proc/cpuinfo")
-        .filter_map(|f| {
-            if let Ok(line) = f {
-                if line.starts_with("cpu MHz") {
-                    let slice = line
-                        .trim_end()
-                        .trim_start_matches(|c: char| !c.is_ascii_digit());
-                    return Some(
-                        f64::from_str(slice).expect("failed to parse /proc/cpuinfo") * 1e6,
-                    );
-                }
-            };
-            None
-        })
-        .collect();
+/// Find the cpu temperature input, preferring the configured hwmon sensors
+/// over the `x86_pkg_temp` thermal zone.
+fn cpu_temp_file() -> AResult<PathBuf> {
+    let patterns = read_config(|c| c.cpu.temp_sensors.clone());
+    let sensors = hwmon::enumerate(&[SensorKind::Temp]);
+    if let Some(sensor) = hwmon::select(&sensors, &patterns).into_iter().next() {
+        log::info!("use {} as cpu temperature", sensor.id());
+        return Ok(sensor.input);
+    }
 
-    Ok(freqs)
+    temp::match_type_dir("x86_pkg_temp").map(|mut p| {
+        p.push("temp");
+        p
+    })
 }
 
-#[derive(Debug, Clone, Copy)]
-struct CpuTime {
-    idle: usize,
-    non_idle: usize,
-    user: usize,
-    system_total: usize,
+/// List the cpufreq policy directories, one per group of cpus sharing a clock.
+fn cpufreq_policies() -> Vec<PathBuf> {
+    glob(CPUFREQ_POLICY_GLOB)
+        .map(|paths| paths.map_while(Result::ok).collect())
+        .unwrap_or_default()
 }
 
-impl CpuTime {
-    fn from_str(s: &str) -> Option<Self> {
-        let mut s = s.trim().split_ascii_whitespace();
-        let user = usize::from_str(s.next()?).ok()?;
-        let nice = usize::from_str(s.next()?).ok()?;
-        let system = usize::from_str(s.next()?).ok()?;
-        let idle = usize::from_str(s.next()?).ok()?;
-        let iowait = usize::from_str(s.next()?).ok()?;
-        let irq = usize::from_str(s.next()?).ok()?;
-        let softirq = usize::from_str(s.next()?).ok()?;
-
-        let system_total = nice + system + irq + softirq;
-        Some(Self {
-            idle: idle + iowait,
-            non_idle: user + system_total,
-            user,
-            system_total,
-        })
-    }
+/// Keep the `scaling_cur_freq` of every policy open for sampling.
+fn open_frequencies(policies: &[PathBuf]) -> Vec<ProcFile> {
+    policies
+        .iter()
+        .filter_map(|policy| ProcFile::open(policy.join("scaling_cur_freq")).ok())
+        .collect()
+}
 
-    fn utilization(&self, old: Self) -> f64 {
-        let elapsed = (self.idle + self.non_idle).saturating_sub(old.idle + old.non_idle);
-        if elapsed == 0 {
-            0.0
-        } else {
-            ((self.non_idle - old.non_idle) as f64 / elapsed as f64).clamp(0., 1.)
-        }
+// Read frequencies (read in kHz, store in Hz)
+fn read_frequencies(files: &mut [ProcFile]) -> AResult<Vec<f64>> {
+    let mut freqs = Vec::with_capacity(files.len());
+    for file in files {
+        let khz: f64 =
+            procparse::number(file.read()?).ok_or(aanyhow!("invalid scaling_cur_freq"))?;
+        freqs.push(khz * 1e3);
     }
 
-    fn utilization_user_and_system(&self, old: Self) -> (f64, f64) {
-        let elapsed = (self.idle + self.non_idle).saturating_sub(old.idle + old.non_idle);
-        if elapsed == 0 {
-            (0.0, 0.)
-        } else {
-            (
-                ((self.user - old.user) as f64 / elapsed as f64).clamp(0., 1.),
-                ((self.system_total - old.system_total) as f64 / elapsed as f64).clamp(0., 1.),
-            )
-        }
-    }
+    Ok(freqs)
 }
 
-fn read_proc_stat() -> AResult<(CpuTime, Vec<CpuTime>)> {
-    let mut utilizations = Vec::with_capacity(32);
-    let mut total = None;
-
-    fileutil::read_lines("/proc/stat")?.for_each(|l| {
-        {
-            if let Ok(line) = l {
-                // Total time
-                let data = line.trim_start_matches(|c: char| !c.is_ascii_whitespace());
-                if line.starts_with("cpu ") {
-                    if let Some(r) = CpuTime::from_str(data) {
-                        total = Some(r);
-                    }
-                } else if line.starts_with("cpu") {
-                    if let Some(r) = CpuTime::from_str(data) {
-                        utilizations.push(r);
-                    }
-                }
-            }
-        }
-    });
-
-    Ok((total.unwrap(), utilizations))
+/// Fill `cores` with the per cpu times and return the aggregated one.
+fn read_proc_stat(stat: &mut ProcFile, cores: &mut Vec<CpuTime>) -> AResult<CpuTime> {
+    procparse::proc_stat(stat.read()?, cores).ok_or(aanyhow!("unable to parse /proc/stat"))
 }
 
 /// Read the cpu turbo boost status from kernel sys interface
@@ -272,3 +417,101 @@ fn boost_status() -> Option<bool> {
         None
     }
 }
+
+fn read_attr(path: PathBuf) -> Option<String> {
+    fs::read_to_string(path)
+        .ok()
+        .map(|s| s.trim().to_owned())
+        .filter(|s| !s.is_empty())
+}
+
+fn read_scaling_info(policies: &[PathBuf]) -> ScalingInfo {
+    let Some(policy) = policies.first() else {
+        return ScalingInfo::default();
+    };
+
+    let split = |s: Option<String>| -> Vec<String> {
+        s.map(|s| s.split_ascii_whitespace().map(str::to_owned).collect())
+            .unwrap_or_default()
+    };
+
+    ScalingInfo {
+        governor: read_attr(policy.join("scaling_governor")),
+        governors: split(read_attr(policy.join("scaling_available_governors"))),
+        energy_preference: read_attr(policy.join("energy_performance_preference")),
+        energy_preferences: split(read_attr(
+            policy.join("energy_performance_available_preferences"),
+        )),
+    }
+}
+
+/// Write the same value into `attr` of every cpufreq policy.
+fn set_policy_attr(policies: &[PathBuf], attr: &str, value: &str) -> AResult<()> {
+    if policies.is_empty() {
+        Err(aanyhow!("cpufreq is not supported"))?
+    }
+
+    privileged::write_attrs(
+        policies
+            .iter()
+            .map(|p| (p.join(attr), value.to_owned()))
+            .collect(),
+    )
+}
+
+fn fill_scaling_menu(menu: &gtk::Menu, info: &ScalingInfo, sender: &SSender<CpuIn>) {
+    menu.foreach(|child| menu.remove(child));
+
+    let add_section =
+        |title: &str, current: &Option<String>, values: &[String], msg: fn(String) -> CpuIn| {
+            if values.is_empty() {
+                return;
+            }
+            if !menu.children().is_empty() {
+                menu.append(&gtk::SeparatorMenuItem::new());
+            }
+
+            let header = gtk::MenuItem::with_label(title);
+            header.set_sensitive(false);
+            menu.append(&header);
+
+            for value in values {
+                let item = gtk::CheckMenuItem::with_label(value);
+                item.set_draw_as_radio(true);
+                item.set_active(current.as_ref() == Some(value));
+                item.connect_activate(clone!(@strong sender, @strong value => move |_| {
+                    let _ = sender.send_blocking(msg(value.clone()));
+                }));
+                menu.append(&item);
+            }
+        };
+
+    add_section(
+        "Governor",
+        &info.governor,
+        &info.governors,
+        CpuIn::SetGovernor,
+    );
+    add_section(
+        "Energy Preference",
+        &info.energy_preference,
+        &info.energy_preferences,
+        CpuIn::SetEnergyPreference,
+    );
+
+    menu.show_all();
+}
+
+/// Enable or disable turbo boost, going through the privileged helper when
+/// the sys interface is not writable.
+fn set_boost(enable: bool) -> AResult<()> {
+    let attr = if Path::new(CPU_BOOST_PATH).exists() {
+        (CPU_BOOST_PATH, if enable { "1" } else { "0" })
+    } else if Path::new(CPU_NO_TURBO_PATH).exists() {
+        (CPU_NO_TURBO_PATH, if enable { "0" } else { "1" })
+    } else {
+        Err(aanyhow!("cpu boost is not supported"))?
+    };
+
+    privileged::write_attrs(vec![(attr.0.into(), attr.1.to_owned())])
+}
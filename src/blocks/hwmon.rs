@@ -0,0 +1,118 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use chin_tools::{aanyhow, AResult};
+use glob::glob;
+
+use crate::util::{fileutil::ProcFile, procparse};
+
+const HWMON_GLOB: &str = "/sys/class/hwmon/hwmon*";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SensorKind {
+    Temp,
+    Fan,
+}
+
+impl SensorKind {
+    fn prefix(&self) -> &'static str {
+        match self {
+            SensorKind::Temp => "temp",
+            SensorKind::Fan => "fan",
+        }
+    }
+}
+
+/// One `*_input` file of a hwmon chip.
+#[derive(Clone, Debug)]
+pub struct Sensor {
+    /// Driver name of the chip, e.g. `k10temp` or `coretemp`.
+    pub chip: String,
+    /// Content of the matching `*_label` file, or the input name without
+    /// suffix (`temp1`) when the driver provides no label.
+    pub label: String,
+    pub kind: SensorKind,
+    pub input: PathBuf,
+}
+
+impl Sensor {
+    pub fn id(&self) -> String {
+        format!("{}/{}", self.chip, self.label)
+    }
+
+    /// A pattern selects a sensor by `chip/label`, by chip or by label.
+    pub fn matches(&self, pattern: &str) -> bool {
+        pattern == self.chip || pattern == self.label || pattern == self.id()
+    }
+
+    /// Keep the input open, see [`Sensor::read`].
+    pub fn open(&self) -> AResult<ProcFile> {
+        Ok(ProcFile::open(&self.input)?)
+    }
+
+    /// Temperatures in degrees Celsius, fans in RPM.
+    pub fn read(&self, input: &mut ProcFile) -> AResult<f64> {
+        let value: f64 = procparse::number(input.read()?)
+            .ok_or(aanyhow!("invalid value in {}", self.input.display()))?;
+        Ok(match self.kind {
+            SensorKind::Temp => value / 1000.,
+            SensorKind::Fan => value,
+        })
+    }
+}
+
+/// List every sensor of the given kinds, ordered by chip and input.
+pub fn enumerate(kinds: &[SensorKind]) -> Vec<Sensor> {
+    let mut sensors = vec![];
+    let Ok(chips) = glob(HWMON_GLOB) else {
+        return sensors;
+    };
+
+    for chip_dir in chips.map_while(Result::ok) {
+        let Some(chip) = read_trimmed(&chip_dir.join("name")) else {
+            continue;
+        };
+
+        for kind in kinds {
+            let pattern = format!("{}/{}*_input", chip_dir.display(), kind.prefix());
+            let Ok(inputs) = glob(&pattern) else {
+                continue;
+            };
+
+            for input in inputs.map_while(Result::ok) {
+                let Some(base) = input
+                    .file_name()
+                    .and_then(|f| f.to_str())
+                    .and_then(|f| f.strip_suffix("_input"))
+                    .map(str::to_owned)
+                else {
+                    continue;
+                };
+
+                let label = read_trimmed(&chip_dir.join(format!("{}_label", base))).unwrap_or(base);
+                sensors.push(Sensor {
+                    chip: chip.clone(),
+                    label,
+                    kind: *kind,
+                    input,
+                });
+            }
+        }
+    }
+
+    sensors
+}
+
+/// Pick the first sensor matching each pattern, keeping the pattern order.
+pub fn select(sensors: &[Sensor], patterns: &[String]) -> Vec<Sensor> {
+    patterns
+        .iter()
+        .filter_map(|pattern| sensors.iter().find(|s| s.matches(pattern)).cloned())
+        .collect()
+}
+
+fn read_trimmed(path: &Path) -> Option<String> {
+    fs::read_to_string(path).ok().map(|s| s.trim().to_owned())
+}
@@ -0,0 +1,460 @@
+use std::{
+    collections::VecDeque,
+    fs, io,
+    net::{IpAddr, SocketAddr, TcpStream, ToSocketAddrs, UdpSocket},
+    os::fd::{FromRawFd, OwnedFd},
+    thread,
+    time::{Duration, Instant},
+};
+
+use chin_tools::{aanyhow, AResult};
+
+use crate::config::{read_config, LatencyConfig};
+use crate::prelude::*;
+use crate::widgets::chart::{Chart, Column};
+use crate::window::WidgetShareInfo;
+
+use super::rtnl::Rtnl;
+use super::Block;
+
+const RESOLV_CONF: &str = "/etc/resolv.conf";
+const ICMP_ECHO_REQUEST: u8 = 8;
+const ICMP_ECHO_REPLY: u8 = 0;
+const ICMPV6_ECHO_REQUEST: u8 = 128;
+const ICMPV6_ECHO_REPLY: u8 = 129;
+
+/// What a latency block measures, parsed from the `latency.targets` config.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Target {
+    /// Gateway of the default route, pinged.
+    Gateway,
+    /// First nameserver of /etc/resolv.conf, pinged.
+    Dns,
+    /// A host pinged over ICMP.
+    Icmp(String),
+    /// A `host:port` timed with a TCP connect.
+    Tcp(String),
+}
+
+impl Target {
+    pub fn parse(target: &str) -> Self {
+        match target {
+            "gateway" => Target::Gateway,
+            "dns" => Target::Dns,
+            // Bare IPv6 addresses are full of colons too.
+            _ if target.parse::<IpAddr>().is_ok() => Target::Icmp(target.to_owned()),
+            _ if target.parse::<SocketAddr>().is_ok() => Target::Tcp(target.to_owned()),
+            _ => match target.rsplit_once(':') {
+                Some((_, port)) if port.parse::<u16>().is_ok() => Target::Tcp(target.to_owned()),
+                _ => Target::Icmp(target.to_owned()),
+            },
+        }
+    }
+
+    fn name(&self) -> &str {
+        match self {
+            Target::Gateway => "gateway",
+            Target::Dns => "dns",
+            Target::Icmp(host) | Target::Tcp(host) => host,
+        }
+    }
+}
+
+/// Outcome of one probe.
+#[derive(Clone, Debug)]
+pub struct Sample {
+    /// Address the target resolved to.
+    pub addr: Option<SocketAddr>,
+    /// Round trip time, `None` when the probe was lost.
+    pub rtt: Option<Duration>,
+    /// Why the probe could not be sent.
+    pub error: Option<String>,
+}
+
+/// Sends the probes, owning the ICMP sockets so they are only created once.
+struct Prober {
+    rtnl: Option<Rtnl>,
+    icmp_v4: Option<UdpSocket>,
+    icmp_v6: Option<UdpSocket>,
+    sequence: u16,
+    timeout: Duration,
+}
+
+impl Prober {
+    fn new(timeout: Duration) -> Self {
+        Self {
+            rtnl: None,
+            icmp_v4: None,
+            icmp_v6: None,
+            sequence: 0,
+            timeout,
+        }
+    }
+
+    fn probe(&mut self, target: &Target) -> Sample {
+        let addr = match self.resolve(target) {
+            Ok(addr) => addr,
+            Err(err) => {
+                return Sample {
+                    addr: None,
+                    rtt: None,
+                    error: Some(err.to_string()),
+                }
+            }
+        };
+
+        let rtt = match target {
+            Target::Tcp(_) => Ok(self.tcp(addr)),
+            _ => self.icmp(addr.ip()),
+        };
+        match rtt {
+            Ok(rtt) => Sample {
+                addr: Some(addr),
+                rtt,
+                error: None,
+            },
+            Err(err) => Sample {
+                addr: Some(addr),
+                rtt: None,
+                error: Some(err.to_string()),
+            },
+        }
+    }
+
+    /// Resolved before every probe, the gateway and nameserver follow
+    /// network changes.
+    fn resolve(&mut self, target: &Target) -> AResult<SocketAddr> {
+        let ip = match target {
+            Target::Gateway => {
+                if self.rtnl.is_none() {
+                    self.rtnl = Some(Rtnl::connect()?);
+                }
+                let links = self.rtnl.as_mut().map(Rtnl::links).transpose()?;
+                links
+                    .into_iter()
+                    .flatten()
+                    .find_map(|link| link.gateway)
+                    .ok_or(aanyhow!("no default gateway"))?
+            }
+            Target::Dns => fs::read_to_string(RESOLV_CONF)?
+                .lines()
+                .filter_map(|line| line.trim().strip_prefix("nameserver"))
+                .find_map(|server| server.trim().parse::<IpAddr>().ok())
+                .ok_or(aanyhow!("no nameserver"))?,
+            Target::Icmp(host) => match host.parse::<IpAddr>() {
+                Ok(ip) => ip,
+                Err(_) => (host.as_str(), 0)
+                    .to_socket_addrs()?
+                    .next()
+                    .ok_or(aanyhow!("unable to resolve host"))?
+                    .ip(),
+            },
+            Target::Tcp(host) => {
+                return host
+                    .to_socket_addrs()?
+                    .next()
+                    .ok_or(aanyhow!("unable to resolve host"))
+            }
+        };
+
+        Ok(SocketAddr::new(ip, 0))
+    }
+
+    /// Time a TCP handshake. A refused connection still took a round trip.
+    fn tcp(&self, addr: SocketAddr) -> Option<Duration> {
+        let start = Instant::now();
+        match TcpStream::connect_timeout(&addr, self.timeout) {
+            Ok(_) => Some(start.elapsed()),
+            Err(err) if err.kind() == io::ErrorKind::ConnectionRefused => Some(start.elapsed()),
+            Err(_) => None,
+        }
+    }
+
+    /// Send an echo request over an unprivileged ICMP socket, the kernel
+    /// fills in the identifier and checksum and only hands us our replies.
+    fn icmp(&mut self, ip: IpAddr) -> AResult<Option<Duration>> {
+        let (socket, request, reply) = match ip {
+            IpAddr::V4(_) => (&mut self.icmp_v4, ICMP_ECHO_REQUEST, ICMP_ECHO_REPLY),
+            IpAddr::V6(_) => (&mut self.icmp_v6, ICMPV6_ECHO_REQUEST, ICMPV6_ECHO_REPLY),
+        };
+        let socket = match socket {
+            Some(socket) => socket,
+            None => socket.insert(icmp_socket(ip).map_err(|err| {
+                aanyhow!(
+                    "unable to open an ICMP socket, check net.ipv4.ping_group_range: {}",
+                    err
+                )
+            })?),
+        };
+
+        self.sequence = self.sequence.wrapping_add(1);
+        let sequence = self.sequence.to_be_bytes();
+        let mut packet = [0u8; 16];
+        packet[0] = request;
+        packet[6..8].copy_from_slice(&sequence);
+
+        let start = Instant::now();
+        if let Err(err) = socket.send_to(&packet, SocketAddr::new(ip, 0)) {
+            log::debug!("unable to send echo request to {}: {}", ip, err);
+            return Ok(None);
+        }
+
+        let mut buf = [0u8; 64];
+        loop {
+            let left = self.timeout.saturating_sub(start.elapsed());
+            if left.is_zero() {
+                return Ok(None);
+            }
+            socket.set_read_timeout(Some(left))?;
+
+            match socket.recv_from(&mut buf) {
+                // Replies to earlier, timed out probes are skipped.
+                Ok((len, from))
+                    if len >= 8 && buf[0] == reply && buf[6..8] == sequence && from.ip() == ip =>
+                {
+                    return Ok(Some(start.elapsed()))
+                }
+                Ok(_) => {}
+                Err(err)
+                    if matches!(
+                        err.kind(),
+                        io::ErrorKind::WouldBlock
+                            | io::ErrorKind::TimedOut
+                            | io::ErrorKind::Interrupted
+                    ) => {}
+                Err(err) => {
+                    log::debug!("unable to receive echo reply from {}: {}", ip, err);
+                    return Ok(None);
+                }
+            }
+        }
+    }
+}
+
+fn icmp_socket(ip: IpAddr) -> io::Result<UdpSocket> {
+    let (domain, protocol) = match ip {
+        IpAddr::V4(_) => (libc::AF_INET, libc::IPPROTO_ICMP),
+        IpAddr::V6(_) => (libc::AF_INET6, libc::IPPROTO_ICMPV6),
+    };
+    let fd = unsafe { libc::socket(domain, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, protocol) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // Safety: the descriptor was just created and nothing else owns it. A
+    // datagram ICMP socket is driven with the same calls as a UDP one.
+    Ok(UdpSocket::from(unsafe { OwnedFd::from_raw_fd(fd) }))
+}
+
+#[derive(Clone)]
+pub enum LatencyIn {}
+
+#[derive(Clone)]
+pub enum LatencyOut {
+    Sample(Sample),
+}
+
+pub struct LatencyBlock {
+    dualchannel: DualChannel<LatencyOut, LatencyIn>,
+    target: Target,
+    config: LatencyConfig,
+}
+
+impl LatencyBlock {
+    pub fn new(target: &str) -> Self {
+        LatencyBlock {
+            dualchannel: DualChannel::new(30),
+            target: Target::parse(target),
+            config: read_config(|c| c.latency.clone()),
+        }
+    }
+}
+
+impl Block for LatencyBlock {
+    type Out = LatencyOut;
+    type In = LatencyIn;
+
+    fn run(&mut self) -> AResult<()> {
+        let target = self.target.clone();
+        let interval = Duration::from_millis(self.config.interval_ms);
+        let timeout = Duration::from_millis(self.config.timeout_ms);
+        let sender = self.dualchannel.get_out_sender();
+
+        thread::spawn(move || {
+            let mut prober = Prober::new(timeout);
+            loop {
+                let start = Instant::now();
+                let sample = prober.probe(&target);
+                if let Some(err) = sample.error.as_ref() {
+                    log::debug!("unable to probe {}: {}", target.name(), err);
+                }
+                sender.send(LatencyOut::Sample(sample)).unwrap();
+
+                thread::sleep(interval.saturating_sub(start.elapsed()));
+            }
+        });
+
+        Ok(())
+    }
+
+    fn widget(&self, _share_info: &WidgetShareInfo) -> gtk::Widget {
+        let holder = gtk::Box::builder()
+            .orientation(gtk::Orientation::Horizontal)
+            .hexpand(false)
+            .build();
+
+        let label = gtk::Label::builder().label("-").build();
+        label.style_context().add_class("latency-label");
+
+        let max_ms = self.config.chart_max_ms;
+        let color = RGBA::new(0.3, 0.3, 0.5, 0.6);
+        let column = Column::new("rtt", max_ms, 30, color);
+        let chart = Chart::builder()
+            .with_line_width(1.0)
+            .with_width(30)
+            .with_columns(column.clone());
+        chart.draw_in_seconds(self.config.interval_ms.div_ceil(1000).max(1) as u32);
+
+        holder.add(&chart.drawing_box);
+        holder.add(&label);
+
+        let name = self.target.name().to_owned();
+        let window = self.config.window.max(1);
+        let block = holder.clone();
+        let mut receiver = self.dualchannel.get_out_receiver();
+        MainContext::ref_thread_default().spawn_local(async move {
+            let mut history: VecDeque<Option<f64>> = VecDeque::with_capacity(window);
+            loop {
+                if let Ok(msg) = receiver.recv().await {
+                    match msg {
+                        LatencyOut::Sample(sample) => {
+                            let rtt = sample.rtt.map(|rtt| rtt.as_secs_f64() * 1000.);
+                            if history.len() == window {
+                                history.pop_front();
+                            }
+                            history.push_back(rtt);
+
+                            // Lost probes peak the chart.
+                            column.add_value(rtt.unwrap_or(max_ms).min(max_ms));
+
+                            let style = block.style_context();
+                            match rtt {
+                                Some(rtt) => {
+                                    label.set_label(&format!("{:.0}ms", rtt));
+                                    style.remove_class("latency-lost");
+                                }
+                                None => {
+                                    label.set_label("lost");
+                                    style.add_class("latency-lost");
+                                }
+                            }
+
+                            let received: Vec<f64> = history.iter().flatten().copied().collect();
+                            let loss = (history.len() - received.len()) as f64 * 100.
+                                / history.len() as f64;
+
+                            let mut tooltip = vec![match sample.addr {
+                                Some(addr) if addr.port() != 0 => format!("{} ({})", name, addr),
+                                Some(addr) => format!("{} ({})", name, addr.ip()),
+                                None => name.clone(),
+                            }];
+                            if let Some(rtt) = rtt {
+                                tooltip.push(format!("last: {:.1} ms", rtt));
+                            }
+                            if !received.is_empty() {
+                                let min = received.iter().copied().fold(f64::MAX, f64::min);
+                                let max = received.iter().copied().fold(0., f64::max);
+                                let avg = received.iter().sum::<f64>() / received.len() as f64;
+                                tooltip.push(format!(
+                                    "min/avg/max: {:.1}/{:.1}/{:.1} ms",
+                                    min, avg, max
+                                ));
+                            }
+                            tooltip.push(format!("loss: {:.0}% of {} probes", loss, history.len()));
+                            if let Some(err) = sample.error {
+                                tooltip.push(err);
+                            }
+                            block.set_tooltip_text(Some(tooltip.join("\n").as_str()));
+                        }
+                    }
+                }
+            }
+        });
+
+        holder.upcast()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::TcpListener;
+
+    use super::*;
+
+    #[test]
+    fn parse_targets() {
+        assert_eq!(Target::parse("gateway"), Target::Gateway);
+        assert_eq!(Target::parse("dns"), Target::Dns);
+        assert_eq!(Target::parse("1.1.1.1"), Target::Icmp("1.1.1.1".to_owned()));
+        assert_eq!(
+            Target::parse("2606:4700::1111"),
+            Target::Icmp("2606:4700::1111".to_owned())
+        );
+        assert_eq!(
+            Target::parse("example.com"),
+            Target::Icmp("example.com".to_owned())
+        );
+        assert_eq!(
+            Target::parse("1.1.1.1:53"),
+            Target::Tcp("1.1.1.1:53".to_owned())
+        );
+        assert_eq!(
+            Target::parse("[::1]:22"),
+            Target::Tcp("[::1]:22".to_owned())
+        );
+        assert_eq!(
+            Target::parse("example.com:443"),
+            Target::Tcp("example.com:443".to_owned())
+        );
+        assert_eq!(
+            Target::parse("example.com:http"),
+            Target::Icmp("example.com:http".to_owned())
+        );
+    }
+
+    #[test]
+    fn tcp_probe() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let mut prober = Prober::new(Duration::from_secs(1));
+        let sample = prober.probe(&Target::Tcp(addr.to_string()));
+        assert_eq!(sample.addr, Some(addr));
+        assert!(sample.rtt.is_some());
+        assert!(sample.error.is_none());
+    }
+
+    #[test]
+    fn tcp_probe_closed_port() {
+        // Free a port so nothing listens on it.
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+
+        // The refusal is a round trip, the host is up.
+        let mut prober = Prober::new(Duration::from_secs(1));
+        let sample = prober.probe(&Target::Tcp(addr.to_string()));
+        assert_eq!(sample.addr, Some(addr));
+        assert!(sample.rtt.is_some());
+    }
+
+    #[test]
+    fn unresolvable_target() {
+        let mut prober = Prober::new(Duration::from_millis(100));
+        let sample = prober.probe(&Target::Tcp("localhost:99999".to_owned()));
+        assert!(sample.addr.is_none());
+        assert!(sample.rtt.is_none());
+        assert!(sample.error.is_some());
+    }
+}
@@ -1,24 +1,65 @@
+use std::{cell::RefCell, rc::Rc};
+
 use chin_tools::AResult;
 
+use crate::config::read_config;
+
 use super::{
-    audio::PulseBlock, battery::BatteryBlock, cpu::CpuBlock, memory::MemoryBlock,
-    netspeed::NetspeedBlock, time::TimeBlock, wayland::WaylandBlock, Block,
+    address::AddressBlock, audio::mic::MicBlock, audio::PulseBlock, battery::BatteryBlock,
+    cpu::CpuBlock, latency::LatencyBlock, memory::MemoryBlock, netspeed::NetspeedBlock,
+    power_profile::PowerProfileBlock, psi::PsiBlock, sensors::SensorsBlock, time::TimeBlock,
+    usage::DataUsage, wayland::WaylandBlock, wireless::WirelessBlock, Block,
 };
 
 pub struct BlockManager {
-    pub net_block: NetspeedBlock,
+    pub net_blocks: Vec<NetspeedBlock>,
+    /// One per netspeed block, sharing its interface filter.
+    pub address_blocks: Vec<AddressBlock>,
+    pub wireless_block: WirelessBlock,
+    pub latency_blocks: Vec<LatencyBlock>,
     pub time_block: TimeBlock,
     pub cpu_block: CpuBlock,
     pub battery_block: BatteryBlock,
+    pub power_profile_block: PowerProfileBlock,
     pub memory_block: MemoryBlock,
+    pub psi_block: PsiBlock,
+    pub sensors_block: SensorsBlock,
     pub wayland_block: WaylandBlock,
     pub vol_block: PulseBlock,
+    pub mic_block: MicBlock,
+    usage: Rc<RefCell<DataUsage>>,
 }
 
 impl BlockManager {
     pub fn launch() -> AResult<BlockManager> {
-        let mut net_block = NetspeedBlock::new();
-        net_block.run()?;
+        let mut filters = read_config(|c| c.netspeed.blocks.clone());
+        if filters.is_empty() {
+            filters.push(Default::default());
+        }
+        let usage = Rc::new(RefCell::new(DataUsage::new(read_config(|c| {
+            c.netspeed.usage.clone()
+        }))));
+        let mut net_blocks = vec![];
+        let mut address_blocks = vec![];
+        for filter in filters {
+            let mut address_block = AddressBlock::new(filter.clone());
+            address_block.run()?;
+            address_blocks.push(address_block);
+
+            let mut net_block = NetspeedBlock::new(filter, usage.clone());
+            net_block.run()?;
+            net_blocks.push(net_block);
+        }
+
+        let mut wireless_block = WirelessBlock::new();
+        wireless_block.run()?;
+
+        let mut latency_blocks = vec![];
+        for target in read_config(|c| c.latency.targets.clone()) {
+            let mut latency_block = LatencyBlock::new(&target);
+            latency_block.run()?;
+            latency_blocks.push(latency_block);
+        }
 
         let mut time_block = TimeBlock::new();
         time_block.run()?;
@@ -26,26 +67,52 @@ impl BlockManager {
         let mut cpu_block = CpuBlock::new();
         cpu_block.run()?;
 
-        let mut battery_block = BatteryBlock::new()?;
+        let mut battery_block = BatteryBlock::new();
         battery_block.run()?;
 
+        let mut power_profile_block =
+            PowerProfileBlock::new(read_config(|c| c.power_profile.clone()));
+        power_profile_block.run()?;
+
         let mut memory_block = MemoryBlock::new();
         memory_block.run()?;
 
+        let mut psi_block = PsiBlock::new();
+        psi_block.run()?;
+
+        let mut sensors_block = SensorsBlock::new();
+        sensors_block.run()?;
+
         let mut vol_block = PulseBlock::new();
         vol_block.run()?;
 
+        let mut mic_block = MicBlock::new();
+        mic_block.run()?;
+
         let mut wayland_block = WaylandBlock::new();
         wayland_block.run()?;
 
         Ok(BlockManager {
-            net_block,
+            net_blocks,
+            address_blocks,
+            wireless_block,
+            latency_blocks,
             time_block,
             cpu_block,
             battery_block,
+            power_profile_block,
             memory_block,
+            psi_block,
+            sensors_block,
             wayland_block,
             vol_block,
+            mic_block,
+            usage,
         })
     }
+
+    /// Persist what would otherwise be lost when the bar exits.
+    pub fn shutdown(&self) {
+        self.usage.borrow().persist();
+    }
 }
@@ -1,21 +1,76 @@
 use std::cmp::min;
-use std::str::FromStr;
+
+use glob::glob;
+use human_bytes::human_bytes;
 
 use crate::prelude::*;
 use chin_tools::AResult;
 
-
-
-use crate::window::WidgetShareInfo;
+use crate::config::read_config;
+use crate::util::fileutil::ProcFile;
 use crate::util::gtk_icon_loader::StatusName;
-use crate::util::{fileutil, gtk_icon_loader};
-use crate::widgets::chart::{Chart, Column};
+use crate::util::{gtk_icon_loader, procparse};
+use crate::widgets::chart::{BaselineType, Chart, Column};
+use crate::widgets::process_popup::ProcessPopup;
+use crate::window::WidgetShareInfo;
 
+use super::process::ProcessSort;
 use super::Block;
 
+const MEMINFO: &str = "/proc/meminfo";
+const ZFS_ARCSTATS: &str = "/proc/spl/kstat/zfs/arcstats";
+const ZRAM_MM_STAT_GLOB: &str = "/sys/block/zram*/mm_stat";
+
 #[derive(Clone)]
 pub enum MemoryOut {
     MemoryUsedAndCache(usize, usize, usize), // USED / Cache / total
+    Swap(usize, usize),                      // USED / total
+    Zram(Option<ZramState>),
+}
+
+/// Summed over all zram devices, in bytes.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ZramState {
+    pub orig_data: usize,
+    pub compressed_data: usize,
+    pub mem_used: usize,
+}
+
+impl ZramState {
+    /// Keep the `mm_stat` of every zram device open for sampling.
+    fn open() -> Vec<ProcFile> {
+        glob(ZRAM_MM_STAT_GLOB)
+            .map(|paths| {
+                paths
+                    .map_while(Result::ok)
+                    .filter_map(|path| ProcFile::open(path).ok())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn read(files: &mut [ProcFile]) -> Option<Self> {
+        let mut state: Option<ZramState> = None;
+        for file in files {
+            let Ok(content) = file.read() else {
+                continue;
+            };
+            let mut fields = procparse::numbers(content);
+            let total = state.get_or_insert_with(Default::default);
+            total.orig_data += fields.next().unwrap_or_default();
+            total.compressed_data += fields.next().unwrap_or_default();
+            total.mem_used += fields.next().unwrap_or_default();
+        }
+        state
+    }
+
+    pub fn ratio(&self) -> f64 {
+        if self.compressed_data == 0 {
+            0.
+        } else {
+            self.orig_data as f64 / self.compressed_data as f64
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -41,36 +96,55 @@ impl Block for MemoryBlock {
     fn run(&mut self) -> AResult<()> {
         let sender = self.dualchannel.get_out_sender();
 
+        let mut meminfo = ProcFile::open(MEMINFO)?;
+        let mut arcstats = ProcFile::open(ZFS_ARCSTATS).ok();
+        let mut zram = ZramState::open();
+
         timeout_add_seconds_local(1, move || {
-            let mem_state = Memstate::new().unwrap();
+            let Ok(mem_state) = Memstate::read(&mut meminfo, arcstats.as_mut()) else {
+                return ControlFlow::Continue;
+            };
 
             let mem_total = mem_state.mem_total * 1024;
 
-            // TODO: possibly remove this as it is confusing to have `mem_total_used` and `mem_used`
-            // htop and such only display equivalent of `mem_used`
-            let mem_used = mem_total - mem_state.mem_available * 1024;
-            let mem_cache = mem_state.pagecache * 1024;
-
-            sender
-                .send(MemoryOut::MemoryUsedAndCache(
-                    mem_used, mem_cache, mem_total,
-                ))
-                .unwrap();
+            // The ARC is not part of the page cache, but it can only shrink
+            // down to its minimal size.
+            // see https://github.com/htop-dev/htop/pull/1003
+            let zfs_shrinkable = mem_state
+                .zfs_arc_cache
+                .saturating_sub(mem_state.zfs_arc_min);
 
             // dev note: difference between avail and free:
             // https://git.kernel.org/pub/scm/linux/kernel/git/torvalds/linux.git/commit/?id=34e431b0ae398fc54ea69ff85ec700722c9da773
             // same logic as htop
-            let _mem_avail = ((if mem_state.mem_available != 0 {
+            let mem_avail = (if mem_state.mem_available != 0 {
                 min(mem_state.mem_available, mem_state.mem_total)
             } else {
                 mem_state.mem_free
-            }) as f64)
-                * 1024.0;
+            } + zfs_shrinkable)
+                * 1024;
+
+            let mem_used = mem_total.saturating_sub(mem_avail);
+            let mem_cache = (mem_state.pagecache + zfs_shrinkable) * 1024;
+
+            sender
+                .send(MemoryOut::MemoryUsedAndCache(
+                    mem_used, mem_cache, mem_total,
+                ))
+                .unwrap();
 
             let swap_total = mem_state.swap_total * 1024;
             let swap_free = mem_state.swap_free * 1024;
             let swap_cached = mem_state.swap_cached * 1024;
-            let _swap_used = swap_total - swap_free - swap_cached;
+            let swap_used = swap_total
+                .saturating_sub(swap_free)
+                .saturating_sub(swap_cached);
+
+            sender.send(MemoryOut::Swap(swap_used, swap_total)).unwrap();
+
+            sender
+                .send(MemoryOut::Zram(ZramState::read(&mut zram)))
+                .unwrap();
 
             ControlFlow::Continue
         });
@@ -90,25 +164,83 @@ impl Block for MemoryBlock {
 
         let mem_columns = Column::new("mem", 100.0, 30, RGBA::new(0.2, 0.2, 0.2, 0.6));
         let cache_columns = Column::new("cache", 100.0, 30, RGBA::new(0.5, 0.5, 0.5, 0.6));
+        let swap_columns = Column::new("swap", 100.0, 30, RGBA::new(0.7, 0.2, 0.1, 0.4))
+            .with_baseline(BaselineType::FixedPercent(0.));
         let chart = Chart::builder()
             .with_width(30)
             .with_line_width(1.0)
             .with_columns(mem_columns.clone())
-            .with_columns(cache_columns.clone());
+            .with_columns(cache_columns.clone())
+            .with_columns(swap_columns.clone());
         chart.draw_in_seconds(1);
 
+        let swap_label = gtk::Label::builder().build();
+        swap_label.style_context().add_class("memory-swap");
+
         holder.pack_start(&icon, false, false, 0);
-        holder.pack_end(&chart.drawing_box, false, false, 0);
+        holder.pack_start(&chart.drawing_box, false, false, 0);
+        holder.pack_start(&swap_label, false, false, 0);
+
+        let holder = EventBox::builder().child(&holder).build();
+
+        let processes = ProcessPopup::new(
+            &holder,
+            ProcessSort::Memory,
+            read_config(|c| c.top_processes),
+        );
+        holder.connect_button_release_event(move |_, v1| match v1.button() {
+            1 => {
+                processes.toggle();
+                Propagation::Stop
+            }
+            _ => Propagation::Proceed,
+        });
 
+        let block = holder.clone();
         MainContext::ref_thread_default().spawn_local(async move {
+            let mut memory = String::new();
+            let mut swap = String::new();
+            let mut zram = String::new();
             loop {
                 if let Ok(msg) = receiver.recv().await {
                     match msg {
                         MemoryOut::MemoryUsedAndCache(used, cache, total) => {
                             cache_columns.add_value(((cache * 100) / total) as f64);
                             mem_columns.add_value(((used * 100) / total) as f64);
+                            memory = format!(
+                                "memory: {} / {}\ncache: {}",
+                                human_bytes(used as f64),
+                                human_bytes(total as f64),
+                                human_bytes(cache as f64)
+                            );
+                        }
+                        MemoryOut::Swap(used, total) => {
+                            swap_label.set_visible(total > 0);
+                            if total == 0 {
+                                swap.clear();
+                                continue;
+                            }
+                            swap_columns.add_value(((used * 100) / total) as f64);
+                            swap_label.set_label(human_bytes(used as f64).as_str());
+                            swap = format!(
+                                "\nswap: {} / {}",
+                                human_bytes(used as f64),
+                                human_bytes(total as f64)
+                            );
+                        }
+                        MemoryOut::Zram(state) => {
+                            zram = state.map_or_else(String::new, |z| {
+                                format!(
+                                    "\nzram: {} in {} ({:.1}x)",
+                                    human_bytes(z.orig_data as f64),
+                                    human_bytes(z.mem_used as f64),
+                                    z.ratio()
+                                )
+                            });
                         }
                     }
+
+                    block.set_tooltip_text(Some(format!("{}{}{}", memory, swap, zram).as_str()));
                 }
             }
         });
@@ -134,63 +266,42 @@ struct Memstate {
 }
 
 impl Memstate {
-    fn new() -> AResult<Self> {
+    fn read(meminfo: &mut ProcFile, arcstats: Option<&mut ProcFile>) -> AResult<Self> {
         // Reference: https://www.kernel.org/doc/Documentation/filesystems/proc.txt
 
         let mut mem_state = Memstate::default();
 
-        fileutil::read_lines("/proc/meminfo")
-            .expect("unable to open /proc/meminfo ?")
-            .for_each(|line| {
-                let line = line.unwrap_or("".to_string());
-
-                let mut words = line.split_whitespace();
+        for (name, val) in procparse::meminfo(meminfo.read()?) {
+            match name {
+                "MemTotal" => mem_state.mem_total = val,
+                "MemFree" => mem_state.mem_free = val,
+                "MemAvailable" => mem_state.mem_available = val,
+                "Buffers" => mem_state.buffers = val,
+                "Cached" => mem_state.pagecache = val,
+                "SReclaimable" => mem_state.s_reclaimable = val,
+                "Shmem" => mem_state.shmem = val,
+                "SwapTotal" => mem_state.swap_total = val,
+                "SwapFree" => mem_state.swap_free = val,
+                "SwapCached" => mem_state.swap_cached = val,
+                _ => (),
+            }
+        }
 
-                let name = match words.next() {
-                    Some(name) => name,
-                    None => {
-                        return;
-                    }
-                };
-                let val = words
-                    .next()
-                    .and_then(|x| usize::from_str(x).ok())
-                    .expect("failed to parse /proc/meminfo");
+        if mem_state.mem_total == 0 {
+            Err(aanyhow!("failed to parse /proc/meminfo"))?
+        }
 
+        // Values in arcstats are bytes, keep them in kB like meminfo.
+        if let Some(Ok(content)) = arcstats.map(|f| f.read()) {
+            for (name, val) in procparse::kstat(content) {
                 match name {
-                    "MemTotal:" => {
-                        mem_state.mem_total = val;
-                    }
-                    "MemFree:" => {
-                        mem_state.mem_free = val;
-                    }
-                    "MemAvailable:" => {
-                        mem_state.mem_available = val;
-                    }
-                    "Buffers:" => {
-                        mem_state.buffers = val;
-                    }
-                    "Cached:" => {
-                        mem_state.pagecache = val;
-                    }
-                    "SReclaimable:" => {
-                        mem_state.s_reclaimable = val;
-                    }
-                    "Shmem:" => {
-                        mem_state.shmem = val;
-                    }
-                    "SwapTotal:" => {
-                        mem_state.swap_total = val;
-                    }
-                    "SwapFree:" => {
-                        mem_state.swap_free = val;
-                    }
-                    "SwapCached:" => {
-                        mem_state.swap_cached = val;
-                    }
+                    "size" => mem_state.zfs_arc_cache = val / 1024,
+                    "c_min" => mem_state.zfs_arc_min = val / 1024,
                     _ => (),
                 }
-            });
+            }
+        }
+
         Ok(mem_state)
     }
 }
@@ -2,19 +2,30 @@ use chin_tools::AResult;
 
 use crate::window::WidgetShareInfo;
 
-mod audio;
+pub mod address;
+pub mod audio;
 #[allow(dead_code)]
 pub mod battery;
 #[allow(dead_code)]
 pub mod cpu;
+pub mod hwmon;
+pub mod latency;
 pub mod manager;
 #[allow(dead_code)]
 pub mod memory;
 pub mod netspeed;
+pub mod nl80211;
+pub mod power_profile;
+pub mod process;
+pub mod psi;
+pub mod rtnl;
+pub mod sensors;
 
 pub mod temp;
 pub mod time;
+pub mod usage;
 pub mod wayland;
+pub mod wireless;
 
 pub trait Block {
     type Out;
@@ -1,14 +1,19 @@
+use std::{cell::RefCell, collections::HashMap, fs, rc::Rc};
+
 use crate::prelude::*;
 use chin_tools::AResult;
 
 use human_bytes::human_bytes;
-use regex::Regex;
+use regex::RegexSet;
 
+use crate::config::InterfaceFilter;
+use crate::util::fileutil::ProcFile;
 use crate::util::gtk_icon_loader::StatusName;
-use crate::util::{fileutil, gtk_icon_loader};
+use crate::util::{gtk_icon_loader, procparse};
 use crate::widgets::chart::{BaselineType, Chart, Column};
 use crate::window::WidgetShareInfo;
 
+use super::usage::{DataUsage, QuotaUsage, Traffic};
 use super::Block;
 
 const NET_DEV: &str = "/proc/net/dev";
@@ -19,64 +24,134 @@ pub enum NetspeedIn {}
 #[derive(Clone)]
 pub enum NetspeedOut {
     NetspeedDiff(f64, f64),
+    /// Upload and download speed of every counted interface.
+    Interfaces(Vec<(Rc<str>, f64, f64)>),
+    /// Traffic of the counted interfaces today and in the billing period.
+    Usage {
+        daily: Traffic,
+        monthly: Traffic,
+        quota: Option<QuotaUsage>,
+    },
+}
+
+/// Compiled form of an [`InterfaceFilter`].
+pub struct InterfaceMatcher {
+    allow: RegexSet,
+    deny: RegexSet,
+}
+
+impl InterfaceMatcher {
+    pub fn new(filter: &InterfaceFilter) -> Self {
+        let compile = |patterns: &[String]| {
+            RegexSet::new(patterns).unwrap_or_else(|err| {
+                log::error!("invalid interface pattern: {}", err);
+                RegexSet::empty()
+            })
+        };
+
+        Self {
+            allow: compile(&filter.allow),
+            deny: compile(&filter.deny),
+        }
+    }
+
+    pub fn is_counted(&self, interface: &str) -> bool {
+        if self.allow.is_empty() {
+            !self.deny.is_match(interface)
+        } else {
+            self.allow.is_match(interface)
+        }
+    }
+}
+
+struct InterfaceCounters {
+    /// Read once when the interface shows up, it stays the same across
+    /// renames.
+    ifindex: Option<u32>,
+    download: usize,
+    upload: usize,
 }
 
+/// Last byte counters of an interface, `None` when the filter excludes it so
+/// the patterns only run once per interface.
+type Counters = HashMap<Rc<str>, Option<InterfaceCounters>>;
+
 pub struct NetspeedBlock {
     dualchannel: DualChannel<NetspeedOut, NetspeedIn>,
+    filter: InterfaceFilter,
+    usage: Rc<RefCell<DataUsage>>,
 }
 
 impl NetspeedBlock {
-    pub fn new() -> Self {
+    pub fn new(filter: InterfaceFilter, usage: Rc<RefCell<DataUsage>>) -> Self {
         let dualchannel = DualChannel::new(100);
 
-        NetspeedBlock { dualchannel }
+        NetspeedBlock {
+            dualchannel,
+            filter,
+            usage,
+        }
     }
 
-    fn read_total_bytes() -> (usize, usize) {
-        let mut total_download = 0;
-        let mut total_upload = 0;
-
-        if let Ok(lines) = fileutil::read_lines(NET_DEV) {
-            // Consumes the iterator, returns an (Optional) String
-            let reg = Regex::new(r"\s+").expect("Invalid regex");
-            for line in lines.map_while(Result::ok) {
-                let fields = reg
-                    .split(line.trim())
-                    .map(|x| x.to_string())
-                    .collect::<Vec<String>>();
-                // line.trim().split(" ").collect::<Vec<&str>>();
-                if fields.len() <= 10 {
-                    continue;
-                }
-
-                if !Regex::new(r"^[0-9]+$").unwrap().is_match(&fields[1]) {
-                    continue;
-                }
-
-                let cidb: usize = fields[1].parse().unwrap();
-                let diub: usize = fields[9].parse().unwrap();
-
-                let interface = &fields[0];
-                if Regex::new(r"^lo:?").unwrap().is_match(interface) ||
-                    // Created by python-based bandwidth manager "traffictoll".
-                    Regex::new(r"^ifb[0-9]+:?").unwrap().is_match(interface) ||
-                    // Created by lxd container manager.
-                    Regex::new(r"^lxdbr[0-9]+:?").unwrap().is_match(interface) ||
-                    Regex::new(r"^virbr[0-9]+:?").unwrap().is_match(interface) ||
-                    Regex::new(r"^br[0-9]+:?").unwrap().is_match(interface) ||
-                    Regex::new(r"^vnet[0-9]+:?").unwrap().is_match(interface) ||
-                    Regex::new(r"^tun[0-9]+:?").unwrap().is_match(interface) ||
-                    Regex::new(r"^tap[0-9]+:?").unwrap().is_match(interface)
-                {
-                    continue;
+    /// Update `counters` from /proc/net/dev and return the received and
+    /// transmitted bytes of every counted interface since the last read.
+    /// The raw counters are handed to `usage` which keeps its own totals.
+    fn read_interface_bytes(
+        net_dev: &mut ProcFile,
+        matcher: &InterfaceMatcher,
+        counters: &mut Counters,
+        usage: &mut DataUsage,
+    ) -> AResult<Vec<(Rc<str>, usize, usize)>> {
+        let content = net_dev.read()?;
+        let mut diffs = Vec::with_capacity(counters.len());
+        let mut present = 0;
+
+        for (interface, download, upload) in procparse::net_dev(content) {
+            present += 1;
+            let name = match counters.get_key_value(interface) {
+                Some((name, _)) => name.clone(),
+                None => {
+                    let name: Rc<str> = Rc::from(interface);
+                    // New interfaces report zero for their first tick.
+                    let last = matcher.is_counted(interface).then(|| InterfaceCounters {
+                        ifindex: fs::read_to_string(format!(
+                            "/sys/class/net/{}/ifindex",
+                            interface
+                        ))
+                        .ok()
+                        .and_then(|s| procparse::number(&s)),
+                        download,
+                        upload,
+                    });
+                    counters.insert(name.clone(), last);
+                    name
                 }
-
-                total_download += cidb;
-                total_upload += diub;
+            };
+
+            let Some(Some(last)) = counters.get_mut(interface) else {
+                continue;
+            };
+            // Counters going back (e.g. a re-created device) report zero.
+            diffs.push((
+                name,
+                download.saturating_sub(last.download),
+                upload.saturating_sub(last.upload),
+            ));
+            last.download = download;
+            last.upload = upload;
+            if let Some(ifindex) = last.ifindex {
+                usage.update(ifindex, interface, download as u64, upload as u64);
             }
         }
 
-        (total_download, total_upload)
+        // Forget interfaces which are gone, names may be reused later.
+        if present != counters.len() {
+            counters.retain(|name, _| {
+                procparse::net_dev(content).any(|(interface, ..)| interface == name.as_ref())
+            });
+        }
+
+        Ok(diffs)
     }
 }
 
@@ -85,34 +160,62 @@ impl Block for NetspeedBlock {
     type Out = NetspeedOut;
 
     fn run(&mut self) -> AResult<()> {
-        let (mut last_total_download, mut last_total_upload) = Self::read_total_bytes();
-        let mut last_update_time = None;
+        let matcher = InterfaceMatcher::new(&self.filter);
+        let mut net_dev = ProcFile::open(NET_DEV)?;
+        let mut counters = Counters::new();
+        let usage = self.usage.clone();
+        Self::read_interface_bytes(
+            &mut net_dev,
+            &matcher,
+            &mut counters,
+            &mut usage.borrow_mut(),
+        )?;
+        let mut last_update_time = std::time::SystemTime::now();
 
         let sender = self.dualchannel.get_out_sender();
 
         timeout_add_seconds_local(1, move || {
-            let (download, upload) = Self::read_total_bytes();
+            let mut usage = usage.borrow_mut();
+            let Ok(diffs) =
+                Self::read_interface_bytes(&mut net_dev, &matcher, &mut counters, &mut usage)
+            else {
+                return ControlFlow::Continue;
+            };
+            if usage.enabled() {
+                usage.flush();
+                let (daily, monthly) = usage.usage(|interface| matcher.is_counted(interface));
+                sender
+                    .send(Self::Out::Usage {
+                        daily,
+                        monthly,
+                        quota: usage.quota(),
+                    })
+                    .unwrap();
+            }
             let now = std::time::SystemTime::now();
-            if let Some(last) = last_update_time.replace(now) {
-                let diff_download_bytes = download - last_total_download;
-                let diff_upload_bytes = upload - last_total_upload;
-
-                let diff = now.duration_since(last);
-                if let Ok(dur) = diff {
-                    last_total_download = download;
-                    last_total_upload = upload;
-
-                    let secs = (dur.as_millis() as f64) / 1000.0;
-
-                    let convert = |bytes: usize| -> f64 { (bytes as f64) / secs };
-
-                    sender
-                        .send(Self::Out::NetspeedDiff(
-                            convert(diff_upload_bytes),
-                            convert(diff_download_bytes),
-                        ))
-                        .unwrap();
+            let last = std::mem::replace(&mut last_update_time, now);
+            if let Ok(dur) = now.duration_since(last) {
+                let secs = (dur.as_millis() as f64) / 1000.0;
+
+                let convert = |bytes: usize| -> f64 { (bytes as f64) / secs };
+
+                let mut total_upload = 0.;
+                let mut total_download = 0.;
+                let mut interfaces = Vec::with_capacity(diffs.len());
+                for (interface, download, upload) in diffs {
+                    let up = convert(upload);
+                    let down = convert(download);
+
+                    total_upload += up;
+                    total_download += down;
+                    interfaces.push((interface, up, down));
                 }
+                interfaces.sort_by(|a, b| a.0.cmp(&b.0));
+
+                sender
+                    .send(Self::Out::NetspeedDiff(total_upload, total_download))
+                    .unwrap();
+                sender.send(Self::Out::Interfaces(interfaces)).unwrap();
             }
 
             ControlFlow::Continue
@@ -154,8 +257,11 @@ impl Block for NetspeedBlock {
 
         holder.add(&speed_label);
 
+        let block = holder.clone();
         let mut mreceiver = self.dualchannel.get_out_receiver();
         MainContext::ref_thread_default().spawn_local(async move {
+            let mut usage_lines: Vec<String> = vec![];
+            let mut usage_class = None;
             loop {
                 if let Ok(msg) = mreceiver.recv().await {
                     match msg {
@@ -166,6 +272,58 @@ impl Block for NetspeedBlock {
                                 format!("{}\n{} ", human_bytes(up), human_bytes(down)).as_str(),
                             );
                         }
+                        NetspeedOut::Interfaces(interfaces) => {
+                            let tooltip = interfaces
+                                .iter()
+                                .map(|(interface, up, down)| {
+                                    format!(
+                                        "{}: ↑ {}/s ↓ {}/s",
+                                        interface,
+                                        human_bytes(*up),
+                                        human_bytes(*down)
+                                    )
+                                })
+                                .chain(usage_lines.iter().cloned())
+                                .collect::<Vec<String>>()
+                                .join("\n");
+                            block.set_tooltip_text(Some(tooltip.as_str()));
+                        }
+                        NetspeedOut::Usage {
+                            daily,
+                            monthly,
+                            quota,
+                        } => {
+                            let format = |traffic: Traffic| {
+                                format!(
+                                    "↑ {} ↓ {}",
+                                    human_bytes(traffic.tx as f64),
+                                    human_bytes(traffic.rx as f64)
+                                )
+                            };
+                            usage_lines.clear();
+                            usage_lines.push(format!("today: {}", format(daily)));
+                            usage_lines.push(format!("this period: {}", format(monthly)));
+                            if let Some(quota) = quota.as_ref() {
+                                usage_lines.push(format!(
+                                    "quota: {} of {} ({:.0}%)",
+                                    human_bytes(quota.used as f64),
+                                    human_bytes(quota.limit as f64),
+                                    quota.used as f64 * 100. / quota.limit as f64
+                                ));
+                            }
+
+                            let class = quota.and_then(|q| q.level.css_class());
+                            if class != usage_class {
+                                let style = block.style_context();
+                                if let Some(old) = usage_class {
+                                    style.remove_class(old);
+                                }
+                                if let Some(new) = class {
+                                    style.add_class(new);
+                                }
+                                usage_class = class;
+                            }
+                        }
                     }
                 }
             }
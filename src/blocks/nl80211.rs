@@ -0,0 +1,167 @@
+use chin_tools::AResult;
+use neli::{
+    consts::{
+        nl::{NlmF, NlmFFlags},
+        socket::NlFamily,
+    },
+    genl::{Genlmsghdr, Nlattr},
+    neli_enum,
+    nl::{NlPayload, Nlmsghdr},
+    socket::NlSocketHandle,
+    types::GenlBuffer,
+};
+
+const NL80211_FAMILY: &str = "nl80211";
+
+// Constants from include/uapi/linux/nl80211.h, only the ones we query.
+
+#[neli_enum(serialized_type = "u8")]
+enum Nl80211Cmd {
+    Unspecified = 0,
+    GetInterface = 5,
+    GetStation = 17,
+}
+impl neli::consts::genl::Cmd for Nl80211Cmd {}
+
+#[neli_enum(serialized_type = "u16")]
+enum Nl80211Attr {
+    Unspecified = 0,
+    Ifindex = 3,
+    Mac = 6,
+    StaInfo = 21,
+    WiphyFreq = 38,
+    Ssid = 52,
+}
+impl neli::consts::genl::NlAttrType for Nl80211Attr {}
+
+#[neli_enum(serialized_type = "u16")]
+enum Nl80211StaInfo {
+    Invalid = 0,
+    Signal = 7,
+    TxBitrate = 8,
+}
+impl neli::consts::genl::NlAttrType for Nl80211StaInfo {}
+
+#[neli_enum(serialized_type = "u16")]
+enum Nl80211RateInfo {
+    Invalid = 0,
+    Bitrate = 1,
+    Bitrate32 = 5,
+}
+impl neli::consts::genl::NlAttrType for Nl80211RateInfo {}
+
+type Nl80211Msg = Genlmsghdr<Nl80211Cmd, Nl80211Attr>;
+
+/// What the kernel knows about the current association of an interface.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LinkInfo {
+    pub ssid: Option<String>,
+    pub bssid: Option<String>,
+    /// Channel frequency in MHz.
+    pub frequency: Option<u32>,
+    /// Signal of the access point in dBm.
+    pub signal: Option<i8>,
+    /// Transmit bitrate in Mbit/s.
+    pub bitrate: Option<f64>,
+}
+
+/// A generic netlink socket bound to the nl80211 family.
+pub struct Nl80211 {
+    socket: NlSocketHandle,
+    family: u16,
+}
+
+impl Nl80211 {
+    pub fn connect() -> AResult<Self> {
+        let mut socket = NlSocketHandle::connect(NlFamily::Generic, None, &[])?;
+        let family = socket.resolve_genl_family(NL80211_FAMILY)?;
+
+        Ok(Self { socket, family })
+    }
+
+    pub fn link_info(&mut self, ifindex: u32) -> AResult<LinkInfo> {
+        let mut info = LinkInfo::default();
+
+        for msg in self.request(Nl80211Cmd::GetInterface, ifindex, false)? {
+            let attrs = msg.get_attr_handle();
+            info.ssid = attrs
+                .get_attr_payload_as_with_len::<&[u8]>(Nl80211Attr::Ssid)
+                .ok()
+                .map(|ssid| String::from_utf8_lossy(ssid).into_owned());
+            info.frequency = attrs
+                .get_attr_payload_as::<u32>(Nl80211Attr::WiphyFreq)
+                .ok();
+        }
+
+        // A managed interface has exactly one station, its access point.
+        for msg in self.request(Nl80211Cmd::GetStation, ifindex, true)? {
+            let mut attrs = msg.get_attr_handle();
+            info.bssid = attrs
+                .get_attr_payload_as_with_len::<&[u8]>(Nl80211Attr::Mac)
+                .ok()
+                .map(format_mac);
+
+            let Ok(mut station) =
+                attrs.get_nested_attributes::<Nl80211StaInfo>(Nl80211Attr::StaInfo)
+            else {
+                continue;
+            };
+            info.signal = station
+                .get_attr_payload_as::<u8>(Nl80211StaInfo::Signal)
+                .ok()
+                .map(|signal| signal as i8);
+            if let Ok(rate) =
+                station.get_nested_attributes::<Nl80211RateInfo>(Nl80211StaInfo::TxBitrate)
+            {
+                // Both are in units of 100 kbit/s, the 32 bits one is only
+                // sent for rates which do not fit in 16 bits.
+                info.bitrate = rate
+                    .get_attr_payload_as::<u32>(Nl80211RateInfo::Bitrate32)
+                    .or_else(|_| {
+                        rate.get_attr_payload_as::<u16>(Nl80211RateInfo::Bitrate)
+                            .map(u32::from)
+                    })
+                    .ok()
+                    .map(|rate| rate as f64 / 10.);
+            }
+        }
+
+        Ok(info)
+    }
+
+    fn request(&mut self, cmd: Nl80211Cmd, ifindex: u32, dump: bool) -> AResult<Vec<Nl80211Msg>> {
+        let mut attrs = GenlBuffer::new();
+        attrs.push(Nlattr::new(false, false, Nl80211Attr::Ifindex, ifindex)?);
+
+        let flags = if dump {
+            NlmFFlags::new(&[NlmF::Request, NlmF::Dump])
+        } else {
+            NlmFFlags::new(&[NlmF::Request])
+        };
+        let request = Nlmsghdr::new(
+            None,
+            self.family,
+            flags,
+            None,
+            None,
+            NlPayload::Payload(Genlmsghdr::new(cmd, 1, attrs)),
+        );
+        self.socket.send(request)?;
+
+        let mut replies = vec![];
+        for reply in self.socket.iter::<u16, Nl80211Msg>(false) {
+            if let NlPayload::Payload(msg) = reply?.nl_payload {
+                replies.push(msg);
+            }
+        }
+
+        Ok(replies)
+    }
+}
+
+fn format_mac(mac: &[u8]) -> String {
+    mac.iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<String>>()
+        .join(":")
+}
@@ -0,0 +1,406 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc, thread};
+
+use chin_tools::AResult;
+use zbus::{
+    blocking::{connection, Connection},
+    proxy,
+    zvariant::OwnedValue,
+};
+
+use crate::config::PowerProfileConfig;
+use crate::datahodler::channel::{DualChannel, SSender};
+use crate::prelude::*;
+use crate::util::gtk_icon_loader::{load_fixed_status_surface, StatusName};
+use crate::window::WidgetShareInfo;
+
+use super::Block;
+
+/// power-profiles-daemon took the UPower name in 0.20 and still answers on
+/// the old one, older releases only know the old one. The interface is
+/// named after the service.
+const SERVICES: [(&str, &str); 2] = [
+    (
+        "org.freedesktop.UPower.PowerProfiles",
+        "/org/freedesktop/UPower/PowerProfiles",
+    ),
+    ("net.hadess.PowerProfiles", "/net/hadess/PowerProfiles"),
+];
+
+#[proxy(
+    interface = "org.freedesktop.UPower.PowerProfiles",
+    default_service = "org.freedesktop.UPower.PowerProfiles",
+    default_path = "/org/freedesktop/UPower/PowerProfiles"
+)]
+trait PowerProfiles {
+    #[zbus(property)]
+    fn active_profile(&self) -> zbus::Result<String>;
+
+    #[zbus(property)]
+    fn set_active_profile(&self, profile: &str) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn profiles(&self) -> zbus::Result<Vec<HashMap<String, OwnedValue>>>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Profile {
+    PowerSaver,
+    Balanced,
+    Performance,
+}
+
+impl Profile {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "power-saver" => Some(Profile::PowerSaver),
+            "balanced" => Some(Profile::Balanced),
+            "performance" => Some(Profile::Performance),
+            _ => None,
+        }
+    }
+
+    /// The name used on the bus.
+    fn name(&self) -> &'static str {
+        match self {
+            Profile::PowerSaver => "power-saver",
+            Profile::Balanced => "balanced",
+            Profile::Performance => "performance",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Profile::PowerSaver => "Power saver",
+            Profile::Balanced => "Balanced",
+            Profile::Performance => "Performance",
+        }
+    }
+
+    fn status_name(&self) -> StatusName {
+        match self {
+            Profile::PowerSaver => StatusName::PowerSaver,
+            Profile::Balanced => StatusName::PowerBalanced,
+            Profile::Performance => StatusName::PowerPerformance,
+        }
+    }
+}
+
+/// `system`, `session` or the address of a bus, e.g. a mock service on
+/// `unix:path=/tmp/ppd.sock`.
+fn connect(bus: &str) -> zbus::Result<Connection> {
+    match bus {
+        "system" => Connection::system(),
+        "session" => Connection::session(),
+        address => connection::Builder::address(address)?.build(),
+    }
+}
+
+/// A proxy on whichever name the daemon answers to.
+fn open(conn: &Connection) -> zbus::Result<PowerProfilesProxyBlocking<'static>> {
+    let mut last_err = None;
+    for (service, path) in SERVICES {
+        let proxy = PowerProfilesProxyBlocking::builder(conn)
+            .destination(service)?
+            .path(path)?
+            .interface(service)?
+            .build()?;
+        match proxy.active_profile() {
+            Ok(_) => return Ok(proxy),
+            Err(err) => last_err = Some(err),
+        }
+    }
+    Err(last_err.unwrap_or(zbus::Error::Unsupported))
+}
+
+/// Profiles the daemon offers, in its order. Unknown ones are skipped.
+fn available(proxy: &PowerProfilesProxyBlocking) -> zbus::Result<Vec<Profile>> {
+    Ok(proxy
+        .profiles()?
+        .iter()
+        .filter_map(|profile| {
+            let name = profile.get("Profile")?.downcast_ref::<&str>().ok()?;
+            Profile::parse(name)
+        })
+        .collect())
+}
+
+#[derive(Clone)]
+pub enum PowerProfileIn {
+    Set(Profile),
+}
+
+#[derive(Clone)]
+pub enum PowerProfileOut {
+    Profiles(Vec<Profile>),
+    /// `None` when the daemon reports a profile we do not know.
+    Active(Option<Profile>),
+}
+
+pub struct PowerProfileBlock {
+    dualchannel: DualChannel<PowerProfileOut, PowerProfileIn>,
+    /// `None` when the daemon is not running, the block is hidden then.
+    proxy: Option<PowerProfilesProxyBlocking<'static>>,
+}
+
+impl PowerProfileBlock {
+    pub fn new(config: PowerProfileConfig) -> Self {
+        let proxy = if config.enabled {
+            connect(&config.bus).and_then(|conn| open(&conn))
+        } else {
+            Err(zbus::Error::Unsupported)
+        };
+        let proxy = proxy
+            .inspect_err(|err| log::info!("power profiles are unavailable: {}", err))
+            .ok();
+
+        Self {
+            dualchannel: DualChannel::new(10),
+            proxy,
+        }
+    }
+}
+
+fn fill_profile_menu(
+    menu: &gtk::Menu,
+    profiles: &[Profile],
+    active: Option<Profile>,
+    sender: &SSender<PowerProfileIn>,
+) {
+    menu.foreach(|child| menu.remove(child));
+
+    for profile in profiles.iter().copied() {
+        let item = gtk::CheckMenuItem::with_label(profile.label());
+        item.set_draw_as_radio(true);
+        item.set_active(active == Some(profile));
+        item.connect_activate(clone!(@strong sender => move |_| {
+            let _ = sender.send_blocking(PowerProfileIn::Set(profile));
+        }));
+        menu.append(&item);
+    }
+
+    menu.show_all();
+}
+
+impl Block for PowerProfileBlock {
+    type Out = PowerProfileOut;
+    type In = PowerProfileIn;
+
+    fn run(&mut self) -> AResult<()> {
+        let Some(proxy) = self.proxy.clone() else {
+            return Ok(());
+        };
+
+        let sender = self.dualchannel.get_out_sender();
+        thread::spawn(clone!(@strong proxy, @strong sender => move || {
+            // The first change carries the current value.
+            for _ in proxy.receive_profiles_changed() {
+                match available(&proxy) {
+                    Ok(profiles) => {
+                        sender.send(PowerProfileOut::Profiles(profiles)).unwrap();
+                    }
+                    Err(err) => log::error!("unable to read power profiles: {}", err),
+                }
+            }
+        }));
+        thread::spawn(clone!(@strong proxy => move || {
+            for change in proxy.receive_active_profile_changed() {
+                let active = change.get().ok().and_then(|name| Profile::parse(&name));
+                sender.send(PowerProfileOut::Active(active)).unwrap();
+            }
+        }));
+
+        let in_receiver = self.dualchannel.get_in_receiver();
+        thread::spawn(move || {
+            while let Ok(msg) = in_receiver.recv_blocking() {
+                match msg {
+                    PowerProfileIn::Set(profile) => {
+                        if let Err(err) = proxy.set_active_profile(profile.name()) {
+                            log::error!("unable to set power profile: {}", err);
+                        }
+                    }
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    fn widget(&self, _share_info: &WidgetShareInfo) -> gtk::Widget {
+        let icon = gtk::Image::new();
+        let holder = EventBox::builder().child(&icon).build();
+
+        if self.proxy.is_none() {
+            holder.set_no_show_all(true);
+            return holder.upcast();
+        }
+
+        let profiles: Rc<RefCell<Vec<Profile>>> = Default::default();
+        let active: Rc<RefCell<Option<Profile>>> = Default::default();
+
+        let menu = gtk::Menu::new();
+        menu.set_attach_widget(Some(&holder));
+        let sender = self.dualchannel.in_sender.clone();
+        holder.connect_button_release_event(
+            clone!(@strong profiles, @strong active => move |_, v1| match v1.button() {
+                1 => {
+                    // Cycle through what the daemon offers.
+                    let profiles = profiles.borrow();
+                    let next = active
+                        .borrow()
+                        .and_then(|a| profiles.iter().position(|p| *p == a))
+                        .map_or(0, |i| (i + 1) % profiles.len().max(1));
+                    if let Some(profile) = profiles.get(next) {
+                        let _ = sender.send_blocking(PowerProfileIn::Set(*profile));
+                    }
+                    Propagation::Stop
+                }
+                3 => {
+                    fill_profile_menu(&menu, &profiles.borrow(), *active.borrow(), &sender);
+                    menu.popup_at_pointer(Some(v1));
+                    Propagation::Stop
+                }
+                _ => Propagation::Proceed,
+            }),
+        );
+
+        let block = holder.clone();
+        let mut receiver = self.dualchannel.get_out_receiver();
+        MainContext::ref_thread_default().spawn_local(async move {
+            loop {
+                if let Ok(msg) = receiver.recv().await {
+                    match msg {
+                        PowerProfileOut::Profiles(list) => {
+                            profiles.replace(list);
+                        }
+                        PowerProfileOut::Active(profile) => {
+                            active.replace(profile);
+                            let status =
+                                profile.map_or(StatusName::PowerBalanced, |p| p.status_name());
+                            icon.set_from_surface(load_fixed_status_surface(status).as_ref());
+                            let label = profile.map_or("unknown", |p| p.label());
+                            block.set_tooltip_text(Some(&format!("Power profile: {}", label)));
+                        }
+                    }
+                }
+            }
+        });
+
+        holder.upcast()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{os::unix::net::UnixStream, sync::mpsc, time::Duration};
+
+    use zbus::{
+        blocking, interface,
+        zvariant::{OwnedValue, Value},
+        Guid,
+    };
+
+    use super::*;
+
+    struct MockProfiles {
+        active: String,
+    }
+
+    #[interface(name = "net.hadess.PowerProfiles")]
+    impl MockProfiles {
+        #[zbus(property)]
+        fn active_profile(&self) -> String {
+            self.active.clone()
+        }
+
+        #[zbus(property)]
+        fn set_active_profile(&mut self, profile: String) {
+            self.active = profile;
+        }
+
+        #[zbus(property)]
+        fn profiles(&self) -> Vec<HashMap<String, OwnedValue>> {
+            ["power-saver", "balanced", "custom"]
+                .into_iter()
+                .map(|name| {
+                    HashMap::from([(
+                        "Profile".to_owned(),
+                        Value::from(name).try_to_owned().unwrap(),
+                    )])
+                })
+                .collect()
+        }
+    }
+
+    /// A daemon with the old name on the other end of a socket pair.
+    fn mock() -> (blocking::Connection, blocking::Connection) {
+        let (server, client) = UnixStream::pair().unwrap();
+        let guid = Guid::generate();
+        let server = std::thread::spawn(move || {
+            blocking::connection::Builder::async_io_unix_stream(server)
+                .server(guid)
+                .unwrap()
+                .p2p()
+                .serve_at(
+                    "/net/hadess/PowerProfiles",
+                    MockProfiles {
+                        active: "balanced".to_owned(),
+                    },
+                )
+                .unwrap()
+                .build()
+                .unwrap()
+        });
+        let client = blocking::connection::Builder::async_io_unix_stream(client)
+            .p2p()
+            .build()
+            .unwrap();
+        (server.join().unwrap(), client)
+    }
+
+    #[test]
+    fn test_profiles_from_mock() {
+        let (_server, client) = mock();
+        let proxy = open(&client).unwrap();
+
+        assert_eq!(
+            available(&proxy).unwrap(),
+            vec![Profile::PowerSaver, Profile::Balanced]
+        );
+        assert_eq!(proxy.active_profile().unwrap(), "balanced");
+    }
+
+    #[test]
+    fn test_follow_changes() {
+        let (server, client) = mock();
+        let proxy = open(&client).unwrap();
+
+        let (tx, rx) = mpsc::channel();
+        let watcher = proxy.clone();
+        std::thread::spawn(move || {
+            for change in watcher.receive_active_profile_changed() {
+                tx.send(change.get().unwrap()).unwrap();
+            }
+        });
+        assert_eq!(rx.recv_timeout(Duration::from_secs(2)).unwrap(), "balanced");
+
+        // A change made by someone else.
+        let iface = server
+            .object_server()
+            .interface::<_, MockProfiles>("/net/hadess/PowerProfiles")
+            .unwrap();
+        iface.get_mut().active = "power-saver".to_owned();
+        zbus::block_on(iface.get().active_profile_changed(iface.signal_emitter())).unwrap();
+        assert_eq!(
+            rx.recv_timeout(Duration::from_secs(2)).unwrap(),
+            "power-saver"
+        );
+
+        proxy
+            .set_active_profile(Profile::Performance.name())
+            .unwrap();
+        assert_eq!(
+            rx.recv_timeout(Duration::from_secs(2)).unwrap(),
+            "performance"
+        );
+    }
+}
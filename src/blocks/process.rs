@@ -0,0 +1,141 @@
+use std::{cmp::Reverse, collections::HashMap, fs, time::Instant};
+
+use chin_tools::{aanyhow, AResult};
+use nix::{
+    sys::signal::{self, Signal},
+    unistd::Pid,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProcessSort {
+    Cpu,
+    Memory,
+}
+
+#[derive(Clone, Debug)]
+pub struct ProcessSample {
+    pub pid: i32,
+    pub name: String,
+    /// Percent of one cpu, like top(1) reports it.
+    pub cpu: f64,
+    /// Resident set size in bytes.
+    pub rss: usize,
+}
+
+/// Samples per-process cpu time and memory from /proc.
+///
+/// Cpu usage is the delta of utime + stime between two calls of `sample`, so
+/// the first sample reports zero cpu for every process.
+pub struct ProcessSampler {
+    last_ticks: HashMap<i32, u64>,
+    last_time: Option<Instant>,
+    ticks_per_sec: f64,
+}
+
+impl ProcessSampler {
+    pub fn new() -> Self {
+        let ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+        Self {
+            last_ticks: HashMap::new(),
+            last_time: None,
+            ticks_per_sec: if ticks > 0 { ticks as f64 } else { 100. },
+        }
+    }
+
+    pub fn sample(&mut self) -> Vec<ProcessSample> {
+        let now = Instant::now();
+        let elapsed = self
+            .last_time
+            .replace(now)
+            .map(|last| now.duration_since(last).as_secs_f64())
+            .unwrap_or_default();
+
+        let mut ticks = HashMap::with_capacity(self.last_ticks.len());
+        let mut samples = Vec::with_capacity(self.last_ticks.len());
+
+        let Ok(entries) = fs::read_dir("/proc") else {
+            return samples;
+        };
+
+        for entry in entries.map_while(Result::ok) {
+            let Some(pid) = entry
+                .file_name()
+                .to_str()
+                .and_then(|s| s.parse::<i32>().ok())
+            else {
+                continue;
+            };
+
+            // The process may exit between listing and reading, skip it then.
+            let Ok((name, rss, cpu_ticks)) = read_process(pid) else {
+                continue;
+            };
+
+            let cpu = match self.last_ticks.get(&pid) {
+                Some(last) if elapsed > 0. => {
+                    cpu_ticks.saturating_sub(*last) as f64 / self.ticks_per_sec / elapsed * 100.
+                }
+                _ => 0.,
+            };
+
+            ticks.insert(pid, cpu_ticks);
+            samples.push(ProcessSample {
+                pid,
+                name,
+                cpu,
+                rss,
+            });
+        }
+
+        self.last_ticks = ticks;
+        samples
+    }
+}
+
+/// Keep the `count` heaviest processes by the given criterion.
+pub fn top(mut samples: Vec<ProcessSample>, sort: ProcessSort, count: usize) -> Vec<ProcessSample> {
+    match sort {
+        ProcessSort::Cpu => samples.sort_by(|a, b| b.cpu.total_cmp(&a.cpu).then(b.rss.cmp(&a.rss))),
+        ProcessSort::Memory => samples.sort_by_key(|s| Reverse(s.rss)),
+    }
+    samples.truncate(count);
+    samples
+}
+
+pub fn send_signal(pid: i32, sig: Signal) -> AResult<()> {
+    signal::kill(Pid::from_raw(pid), sig)?;
+    Ok(())
+}
+
+/// Name of a running process, `None` once it exited.
+pub fn process_name(pid: i32) -> Option<String> {
+    read_process(pid).ok().map(|(name, _, _)| name)
+}
+
+/// Read name, rss (bytes) and utime + stime (clock ticks) of a process.
+fn read_process(pid: i32) -> AResult<(String, usize, u64)> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid))?;
+    // The command name may contain spaces and parentheses, the fields we
+    // want come after the last ')'.
+    let rest = stat
+        .rfind(')')
+        .map(|i| &stat[i + 1..])
+        .ok_or(aanyhow!("malformed stat for {}", pid))?;
+    let mut fields = rest.split_ascii_whitespace();
+    // Skip state .. cmajflt, utime is the 14th field of the whole line.
+    let utime: u64 = fields.nth(11).unwrap_or("0").parse()?;
+    let stime: u64 = fields.next().unwrap_or("0").parse()?;
+
+    let mut name = String::new();
+    let mut rss = 0;
+    for line in fs::read_to_string(format!("/proc/{}/status", pid))?.lines() {
+        if let Some(v) = line.strip_prefix("Name:") {
+            name = v.trim().to_owned();
+        } else if let Some(v) = line.strip_prefix("VmRSS:") {
+            rss = v.trim().trim_end_matches("kB").trim().parse::<usize>()? * 1024;
+            break;
+        }
+    }
+
+    Ok((name, rss, utime + stime))
+}
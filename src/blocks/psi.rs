@@ -0,0 +1,325 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    os::{fd::AsFd, unix::fs::OpenOptionsExt},
+    path::Path,
+    str::FromStr,
+    thread,
+};
+
+use chin_tools::AResult;
+use nix::{
+    errno::Errno,
+    poll::{poll, PollFd, PollFlags, PollTimeout},
+};
+
+use crate::config::{read_config, PsiConfig};
+use crate::prelude::*;
+use crate::util::fileutil::ProcFile;
+use crate::util::gtk_icon_loader::{self, StatusName};
+use crate::widgets::chart::{Chart, Column};
+use crate::window::WidgetShareInfo;
+
+use super::Block;
+
+const PSI_DIR: &str = "/proc/pressure";
+const TRIGGER_WINDOW_US: u64 = 2_000_000;
+// Without stalls no trigger fires, re-read now and then so the averages
+// can decay in the bar.
+const TRIGGER_REFRESH_MS: u16 = 10_000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PsiResource {
+    Cpu,
+    Memory,
+    Io,
+}
+
+impl PsiResource {
+    const ALL: [PsiResource; 3] = [PsiResource::Cpu, PsiResource::Memory, PsiResource::Io];
+
+    fn name(&self) -> &'static str {
+        match self {
+            PsiResource::Cpu => "cpu",
+            PsiResource::Memory => "memory",
+            PsiResource::Io => "io",
+        }
+    }
+
+    fn path(&self) -> String {
+        format!("{}/{}", PSI_DIR, self.name())
+    }
+}
+
+/// avg10 of the `some` and `full` lines, in percent.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Pressure {
+    pub some: f64,
+    pub full: f64,
+}
+
+#[derive(Clone)]
+pub enum PsiIn {}
+
+#[derive(Clone)]
+pub enum PsiOut {
+    Pressures([Pressure; 3]),
+}
+
+pub struct PsiBlock {
+    dualchannel: DualChannel<PsiOut, PsiIn>,
+    available: bool,
+}
+
+impl PsiBlock {
+    pub fn new() -> Self {
+        PsiBlock {
+            dualchannel: DualChannel::new(30),
+            available: Path::new(PSI_DIR).exists(),
+        }
+    }
+
+    fn open_pressures() -> AResult<Vec<ProcFile>> {
+        PsiResource::ALL
+            .iter()
+            .map(|resource| Ok(ProcFile::open(resource.path())?))
+            .collect()
+    }
+
+    fn read_pressures(files: &mut [ProcFile]) -> AResult<[Pressure; 3]> {
+        let mut pressures = [Pressure::default(); 3];
+        for (pressure, file) in pressures.iter_mut().zip(files) {
+            *pressure = read_pressure(file.read()?)?;
+        }
+        Ok(pressures)
+    }
+
+    /// Register a trigger on every resource, the returned files must stay
+    /// open for the triggers to live.
+    fn open_triggers(stall_ms: u64) -> AResult<Vec<File>> {
+        let trigger = format!("some {} {}\0", stall_ms * 1000, TRIGGER_WINDOW_US);
+        PsiResource::ALL
+            .iter()
+            .map(|resource| {
+                let mut file = OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .custom_flags(libc::O_NONBLOCK)
+                    .open(resource.path())?;
+                file.write_all(trigger.as_bytes())?;
+                Ok(file)
+            })
+            .collect()
+    }
+}
+
+impl Block for PsiBlock {
+    type Out = PsiOut;
+    type In = PsiIn;
+
+    fn run(&mut self) -> AResult<()> {
+        if !self.available {
+            return Ok(());
+        }
+
+        let sender = self.dualchannel.get_out_sender();
+        let config = read_config(|c| c.psi.clone());
+        let mut files = Self::open_pressures()?;
+
+        if config.triggers {
+            match Self::open_triggers(config.trigger_stall_ms) {
+                Ok(triggers) => {
+                    thread::spawn(move || loop {
+                        let mut fds: Vec<PollFd> = triggers
+                            .iter()
+                            .map(|f| PollFd::new(f.as_fd(), PollFlags::POLLPRI))
+                            .collect();
+                        match poll(&mut fds, PollTimeout::from(TRIGGER_REFRESH_MS)) {
+                            Ok(_) => {}
+                            Err(Errno::EINTR) => continue,
+                            Err(err) => {
+                                log::error!("unable to poll psi triggers: {}", err);
+                                break;
+                            }
+                        }
+
+                        if fds
+                            .iter()
+                            .any(|fd| fd.revents().is_some_and(|r| r.contains(PollFlags::POLLERR)))
+                        {
+                            log::error!("psi triggers are gone");
+                            break;
+                        }
+
+                        if let Ok(pressures) = Self::read_pressures(&mut files) {
+                            sender.send(PsiOut::Pressures(pressures)).unwrap();
+                        }
+                    });
+
+                    return Ok(());
+                }
+                Err(err) => {
+                    log::warn!(
+                        "unable to register psi triggers, fallback to polling: {}",
+                        err
+                    )
+                }
+            }
+        }
+
+        timeout_add_seconds_local(1, move || {
+            if let Ok(pressures) = Self::read_pressures(&mut files) {
+                sender.send(PsiOut::Pressures(pressures)).unwrap();
+            }
+
+            ControlFlow::Continue
+        });
+
+        Ok(())
+    }
+
+    fn widget(&self, _share_info: &WidgetShareInfo) -> gtk::Widget {
+        let holder = gtk::Box::builder()
+            .orientation(gtk::Orientation::Horizontal)
+            .hexpand(false)
+            .build();
+
+        if !self.available {
+            holder.set_no_show_all(true);
+            return holder.upcast();
+        }
+
+        let icon = gtk_icon_loader::load_fixed_status_image(StatusName::Pressure);
+
+        let label = gtk::Label::builder().build();
+        label.style_context().add_class("psi-label");
+
+        let colors = [
+            RGBA::new(0.7, 0.2, 0.1, 0.6),
+            RGBA::new(0.2, 0.2, 0.2, 0.6),
+            RGBA::new(0.3, 0.4, 0.1, 0.6),
+        ];
+        let columns: Vec<Column<f64>> = PsiResource::ALL
+            .iter()
+            .zip(colors)
+            .map(|(resource, color)| {
+                Column::new(resource.name(), 100., 30, color).with_height_percent(1. / 3.)
+            })
+            .collect();
+
+        let chart = columns.iter().fold(
+            Chart::builder().with_width(30).with_line_width(1.),
+            |chart, column| chart.with_columns(column.clone()),
+        );
+        chart.draw_in_seconds(1);
+
+        holder.pack_start(&icon, false, false, 0);
+        holder.pack_start(&chart.drawing_box, false, false, 0);
+        holder.pack_start(&label, false, false, 0);
+
+        let PsiConfig {
+            warning, critical, ..
+        } = read_config(|c| c.psi.clone());
+
+        let mut receiver = self.dualchannel.get_out_receiver();
+        let block = holder.clone();
+        MainContext::ref_thread_default().spawn_local(async move {
+            loop {
+                if let Ok(msg) = receiver.recv().await {
+                    match msg {
+                        PsiOut::Pressures(pressures) => {
+                            for (column, pressure) in columns.iter().zip(pressures) {
+                                column.add_value(pressure.some);
+                            }
+
+                            let worst = pressures.iter().map(|p| p.some).fold(0., f64::max);
+                            label.set_label(format!("{:.1}%", worst).as_str());
+
+                            let tooltip = PsiResource::ALL
+                                .iter()
+                                .zip(pressures)
+                                .map(|(resource, p)| {
+                                    format!(
+                                        "{}: some {:.2}% full {:.2}%",
+                                        resource.name(),
+                                        p.some,
+                                        p.full
+                                    )
+                                })
+                                .collect::<Vec<String>>()
+                                .join("\n");
+                            block.set_tooltip_text(Some(tooltip.as_str()));
+
+                            let style = block.style_context();
+                            style.remove_class("psi-warning");
+                            style.remove_class("psi-critical");
+                            if worst >= critical {
+                                style.add_class("psi-critical");
+                            } else if worst >= warning {
+                                style.add_class("psi-warning");
+                            }
+                        }
+                    }
+                }
+            }
+        });
+
+        holder.upcast()
+    }
+}
+
+/// Parse the content of a /proc/pressure file, e.g.
+/// `some avg10=0.12 avg60=0.05 avg300=0.01 total=12345`.
+fn read_pressure(content: &str) -> AResult<Pressure> {
+    let mut pressure = Pressure::default();
+    for line in content.lines() {
+        let mut fields = line.split_ascii_whitespace();
+        let kind = fields.next();
+        let avg10 = fields
+            .find_map(|f| f.strip_prefix("avg10="))
+            .ok_or(aanyhow!("no avg10 in psi line: {}", line))?;
+        let avg10 = f64::from_str(avg10)?;
+        match kind {
+            Some("some") => pressure.some = avg10,
+            Some("full") => pressure.full = avg10,
+            _ => {}
+        }
+    }
+    Ok(pressure)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn some_and_full() {
+        let pressure = read_pressure(include_str!("fixtures/pressure_memory")).unwrap();
+        assert_eq!(
+            pressure,
+            Pressure {
+                some: 1.53,
+                full: 0.61
+            }
+        );
+    }
+
+    #[test]
+    fn cpu() {
+        let pressure = read_pressure(include_str!("fixtures/pressure_cpu")).unwrap();
+        assert_eq!(pressure.some, 12.04);
+        assert_eq!(pressure.full, 0.);
+
+        // Kernels before 5.13 only have the `some` line for the CPU.
+        let pressure = read_pressure(include_str!("fixtures/pressure_cpu_4.19")).unwrap();
+        assert_eq!(pressure.some, 12.04);
+        assert_eq!(pressure.full, 0.);
+    }
+
+    #[test]
+    fn malformed() {
+        assert!(read_pressure("some avg60=0.87 total=1").is_err());
+        assert!(read_pressure("some avg10=x avg60=0.87").is_err());
+        assert_eq!(read_pressure("").unwrap(), Pressure::default());
+    }
+}
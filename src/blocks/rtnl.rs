@@ -0,0 +1,243 @@
+use std::{
+    net::IpAddr,
+    os::fd::{AsRawFd, BorrowedFd},
+};
+
+use chin_tools::AResult;
+use neli::{
+    consts::{
+        nl::{NlmF, NlmFFlags},
+        rtnl::{
+            Arphrd, Ifa, IfaFFlags, Iff, IffFlags, Ifla, RtAddrFamily, RtScope, RtTable, Rta, Rtm,
+            RtmFFlags, Rtn, Rtprot,
+        },
+        socket::NlFamily,
+    },
+    nl::{NlPayload, Nlmsghdr},
+    rtnl::{Ifaddrmsg, Ifinfomsg, Rtmsg},
+    socket::NlSocketHandle,
+    types::{Buffer, RtBuffer},
+    FromBytesWithInput, Size, ToBytes,
+};
+use nix::{
+    errno::Errno,
+    poll::{poll, PollFd, PollFlags, PollTimeout},
+};
+
+// `ifa_scope` of addresses reachable from everywhere, link and host scoped
+// ones are left out.
+const SCOPE_UNIVERSE: u8 = 0;
+
+/// A route netlink socket subscribed to `RTNLGRP_*` multicast groups, used to
+/// wake up on interface changes instead of polling sysfs.
+pub struct RtnlEvents {
+    socket: NlSocketHandle,
+}
+
+impl RtnlEvents {
+    pub fn subscribe(groups: &[u32]) -> AResult<Self> {
+        let socket = NlSocketHandle::connect(NlFamily::Route, None, groups)?;
+        socket.nonblock()?;
+
+        Ok(Self { socket })
+    }
+
+    /// Wait up to `timeout` for notifications and drain them, returning
+    /// whether any arrived.
+    pub fn wait(&mut self, timeout: PollTimeout) -> AResult<bool> {
+        {
+            // Safety: the socket outlives the poll below.
+            let fd = unsafe { BorrowedFd::borrow_raw(self.socket.as_raw_fd()) };
+            let mut fds = [PollFd::new(fd, PollFlags::POLLIN)];
+            match poll(&mut fds, timeout) {
+                Ok(0) | Err(Errno::EINTR) => return Ok(false),
+                Ok(_) => {}
+                Err(err) => Err(err)?,
+            }
+        }
+
+        let mut received = false;
+        loop {
+            match self.socket.recv::<Rtm, Buffer>() {
+                Ok(Some(_)) => received = true,
+                Ok(None) => break,
+                Err(err) => {
+                    // Most likely ENOBUFS after a burst, the notifications
+                    // are lost but the caller re-reads the state anyway.
+                    log::debug!("unable to read rtnetlink notification: {}", err);
+                    received = true;
+                    break;
+                }
+            }
+        }
+
+        Ok(received)
+    }
+}
+
+/// An address assigned to an interface.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InterfaceAddr {
+    pub addr: IpAddr,
+    pub prefix: u8,
+}
+
+/// State of one interface merged from its link, addresses and routes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Link {
+    pub index: i32,
+    pub name: String,
+    /// Administratively up with a carrier.
+    pub up: bool,
+    pub addresses: Vec<InterfaceAddr>,
+    /// The main table has a default route through this interface.
+    pub default_route: bool,
+    pub gateway: Option<IpAddr>,
+}
+
+/// A route netlink socket used to dump the current state.
+pub struct Rtnl {
+    socket: NlSocketHandle,
+}
+
+impl Rtnl {
+    pub fn connect() -> AResult<Self> {
+        Ok(Self {
+            socket: NlSocketHandle::connect(NlFamily::Route, None, &[])?,
+        })
+    }
+
+    /// Every interface with its global addresses and default routes,
+    /// ordered by index.
+    pub fn links(&mut self) -> AResult<Vec<Link>> {
+        let request = Ifinfomsg::new(
+            RtAddrFamily::Unspecified,
+            Arphrd::Ether,
+            0,
+            IffFlags::empty(),
+            IffFlags::empty(),
+            RtBuffer::new(),
+        );
+        let mut links: Vec<Link> = self
+            .dump(Rtm::Getlink, request)?
+            .into_iter()
+            .filter_map(|msg: Ifinfomsg| {
+                let name = msg
+                    .rtattrs
+                    .get_attr_handle()
+                    .get_attr_payload_as_with_len::<String>(Ifla::Ifname)
+                    .ok()?;
+                Some(Link {
+                    index: msg.ifi_index,
+                    name,
+                    up: msg.ifi_flags.contains(&Iff::Up) && msg.ifi_flags.contains(&Iff::LowerUp),
+                    addresses: vec![],
+                    default_route: false,
+                    gateway: None,
+                })
+            })
+            .collect();
+        links.sort_by_key(|l| l.index);
+
+        let request = Ifaddrmsg {
+            ifa_family: RtAddrFamily::Unspecified,
+            ifa_prefixlen: 0,
+            ifa_flags: IfaFFlags::empty(),
+            ifa_scope: 0,
+            ifa_index: 0,
+            rtattrs: RtBuffer::new(),
+        };
+        for msg in self.dump::<Ifaddrmsg>(Rtm::Getaddr, request)? {
+            if msg.ifa_scope != SCOPE_UNIVERSE {
+                continue;
+            }
+            let attrs = msg.rtattrs.get_attr_handle();
+            // IFA_LOCAL is the own address of point to point links, where
+            // IFA_ADDRESS is the peer.
+            let addr = attrs
+                .get_attr_payload_as_with_len::<&[u8]>(Ifa::Local)
+                .or_else(|_| attrs.get_attr_payload_as_with_len::<&[u8]>(Ifa::Address))
+                .ok()
+                .and_then(to_addr);
+            let link = links.iter_mut().find(|l| l.index == msg.ifa_index);
+            if let (Some(addr), Some(link)) = (addr, link) {
+                link.addresses.push(InterfaceAddr {
+                    addr,
+                    prefix: msg.ifa_prefixlen,
+                });
+            }
+        }
+
+        for family in [RtAddrFamily::Inet, RtAddrFamily::Inet6] {
+            let request = Rtmsg {
+                rtm_family: family,
+                rtm_dst_len: 0,
+                rtm_src_len: 0,
+                rtm_tos: 0,
+                rtm_table: RtTable::Unspec,
+                rtm_protocol: Rtprot::Unspec,
+                rtm_scope: RtScope::Universe,
+                rtm_type: Rtn::Unspec,
+                rtm_flags: RtmFFlags::empty(),
+                rtattrs: RtBuffer::new(),
+            };
+            for msg in self.dump::<Rtmsg>(Rtm::Getroute, request)? {
+                if msg.rtm_table != RtTable::Main
+                    || msg.rtm_type != Rtn::Unicast
+                    || msg.rtm_dst_len != 0
+                {
+                    continue;
+                }
+                let attrs = msg.rtattrs.get_attr_handle();
+                let Ok(oif) = attrs.get_attr_payload_as::<u32>(Rta::Oif) else {
+                    continue;
+                };
+                if let Some(link) = links.iter_mut().find(|l| l.index == oif as i32) {
+                    link.default_route = true;
+                    if link.gateway.is_none() {
+                        link.gateway = attrs
+                            .get_attr_payload_as_with_len::<&[u8]>(Rta::Gateway)
+                            .ok()
+                            .and_then(to_addr);
+                    }
+                }
+            }
+        }
+
+        Ok(links)
+    }
+
+    fn dump<P>(&mut self, ty: Rtm, request: P) -> AResult<Vec<P>>
+    where
+        P: Size + ToBytes + Send + Sync + 'static + for<'a> FromBytesWithInput<'a, Input = usize>,
+    {
+        let request = Nlmsghdr::new(
+            None,
+            ty,
+            NlmFFlags::new(&[NlmF::Request, NlmF::Dump]),
+            None,
+            None,
+            NlPayload::Payload(request),
+        );
+        self.socket.send(request)?;
+
+        let mut replies = vec![];
+        for reply in self.socket.iter::<Rtm, P>(false) {
+            if let NlPayload::Payload(msg) = reply?.nl_payload {
+                replies.push(msg);
+            }
+        }
+
+        Ok(replies)
+    }
+}
+
+fn to_addr(bytes: &[u8]) -> Option<IpAddr> {
+    if let Ok(v4) = <[u8; 4]>::try_from(bytes) {
+        Some(IpAddr::from(v4))
+    } else if let Ok(v6) = <[u8; 16]>::try_from(bytes) {
+        Some(IpAddr::from(v6))
+    } else {
+        None
+    }
+}
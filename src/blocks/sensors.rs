@@ -0,0 +1,148 @@
+use std::time::Duration;
+
+use chin_tools::AResult;
+
+use crate::config::read_config;
+use crate::prelude::*;
+use crate::util::fileutil::ProcFile;
+use crate::util::gtk_icon_loader::{self, StatusName};
+use crate::widgets::chart::{Chart, Column};
+use crate::window::WidgetShareInfo;
+
+use super::hwmon::{self, Sensor, SensorKind};
+use super::Block;
+
+#[derive(Clone)]
+pub enum SensorsIn {}
+
+#[derive(Clone)]
+pub enum SensorsOut {
+    /// One reading per configured sensor, `None` when it failed.
+    Values(Vec<Option<f64>>),
+}
+
+pub struct SensorsBlock {
+    dualchannel: DualChannel<SensorsOut, SensorsIn>,
+    sensors: Vec<Sensor>,
+}
+
+impl SensorsBlock {
+    pub fn new() -> Self {
+        let patterns = read_config(|c| c.sensors.sensors.clone());
+        let sensors = hwmon::select(
+            &hwmon::enumerate(&[SensorKind::Temp, SensorKind::Fan]),
+            &patterns,
+        );
+
+        SensorsBlock {
+            dualchannel: DualChannel::new(30),
+            sensors,
+        }
+    }
+}
+
+impl Block for SensorsBlock {
+    type Out = SensorsOut;
+    type In = SensorsIn;
+
+    fn run(&mut self) -> AResult<()> {
+        if self.sensors.is_empty() {
+            return Ok(());
+        }
+
+        let sensors = self.sensors.clone();
+        let mut inputs: Vec<Option<ProcFile>> = sensors.iter().map(|s| s.open().ok()).collect();
+        let sender = self.dualchannel.get_out_sender();
+        timeout_add_local(Duration::from_millis(1600), move || {
+            let values = sensors
+                .iter()
+                .zip(inputs.iter_mut())
+                .map(|(sensor, input)| sensor.read(input.as_mut()?).ok())
+                .collect();
+            sender.send(SensorsOut::Values(values)).unwrap();
+
+            ControlFlow::Continue
+        });
+
+        Ok(())
+    }
+
+    fn widget(&self, _share_info: &WidgetShareInfo) -> gtk::Widget {
+        let holder = gtk::Box::builder()
+            .orientation(gtk::Orientation::Horizontal)
+            .hexpand(false)
+            .spacing(4)
+            .build();
+
+        if self.sensors.is_empty() {
+            holder.set_no_show_all(true);
+            return holder.upcast();
+        }
+
+        let mut labels = vec![];
+        let mut columns = vec![];
+
+        for sensor in self.sensors.iter() {
+            let (icon, threshold, color) = match sensor.kind {
+                SensorKind::Temp => (StatusName::Thermometer, 100., RGBA::new(0.7, 0.2, 0.1, 0.6)),
+                SensorKind::Fan => (StatusName::Fan, 5000., RGBA::new(0.2, 0.3, 0.6, 0.6)),
+            };
+
+            let sensor_holder = gtk::Box::builder()
+                .orientation(gtk::Orientation::Horizontal)
+                .build();
+            sensor_holder.set_tooltip_text(Some(sensor.id().as_str()));
+
+            let label = gtk::Label::builder().build();
+            label.style_context().add_class("sensor-label");
+
+            let column = Column::new(sensor.label.as_str(), threshold, 30, color);
+            let chart = Chart::builder()
+                .with_width(20)
+                .with_line_width(1.)
+                .with_columns(column.clone());
+            chart.draw_in_seconds(2);
+
+            sensor_holder.pack_start(
+                &gtk_icon_loader::load_fixed_status_image(icon),
+                false,
+                false,
+                0,
+            );
+            sensor_holder.pack_start(&chart.drawing_box, false, false, 0);
+            sensor_holder.pack_start(&label, false, false, 0);
+            holder.pack_start(&sensor_holder, false, false, 0);
+
+            labels.push(label);
+            columns.push(column);
+        }
+
+        let kinds: Vec<SensorKind> = self.sensors.iter().map(|s| s.kind).collect();
+        let mut receiver = self.dualchannel.get_out_receiver();
+        MainContext::ref_thread_default().spawn_local(async move {
+            loop {
+                if let Ok(msg) = receiver.recv().await {
+                    match msg {
+                        SensorsOut::Values(values) => {
+                            for (i, value) in values.into_iter().enumerate() {
+                                let Some(value) = value else {
+                                    labels[i].set_label("-");
+                                    continue;
+                                };
+
+                                columns[i].add_value(value);
+                                let text = match kinds[i] {
+                                    SensorKind::Temp => format!("{:.0}C", value),
+                                    SensorKind::Fan => format!("{:.0}rpm", value),
+                                };
+                                labels[i].set_label(text.as_str());
+                            }
+                        }
+                    }
+                }
+            }
+        });
+
+        holder.upcast()
+    }
+}
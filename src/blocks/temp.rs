@@ -7,6 +7,8 @@ use std::{
 use chin_tools::{aanyhow, AResult};
 use glob::glob;
 
+use crate::util::{fileutil::ProcFile, procparse};
+
 pub fn match_type_dir(type_name: &str) -> AResult<PathBuf> {
     let entries = glob("/sys/class/thermal/thermal_zone*/type")?;
     let mut s = String::new();
@@ -23,13 +25,8 @@ pub fn match_type_dir(type_name: &str) -> AResult<PathBuf> {
     Err(aanyhow!("unable to get dir"))
 }
 
-pub fn read_type_temp(temp_file: &PathBuf) -> AResult<f64> {
-    let mut s = String::new();
-    let file = File::open(temp_file)?;
-    let mut reader = BufReader::new(file);
-
-    let _content_size = reader.read_to_string(&mut s)?;
-    let temp = i64::from_str_radix(s.trim_end(), 10)?;
+pub fn read_type_temp(temp_file: &mut ProcFile) -> AResult<f64> {
+    let temp: i64 = procparse::number(temp_file.read()?).ok_or(aanyhow!("invalid temperature"))?;
 
     Ok(temp as f64 / 1000.)
 }
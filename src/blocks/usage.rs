@@ -0,0 +1,412 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use chin_tools::AResult;
+use chrono::{Datelike, Local, Months, NaiveDate};
+use notify_rust::{Notification, Timeout};
+use regex::RegexSet;
+use serde::{Deserialize, Serialize};
+
+use crate::config::UsageConfig;
+use crate::util::fileutil::state_dir;
+
+const BOOT_ID: &str = "/proc/sys/kernel/random/boot_id";
+const SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Received and transmitted bytes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Traffic {
+    pub rx: u64,
+    pub tx: u64,
+}
+
+impl Traffic {
+    pub fn total(&self) -> u64 {
+        self.rx + self.tx
+    }
+
+    fn add(&mut self, other: Traffic) {
+        self.rx += other.rx;
+        self.tx += other.tx;
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QuotaLevel {
+    #[default]
+    Normal,
+    Warning,
+    Critical,
+}
+
+impl QuotaLevel {
+    pub fn css_class(&self) -> Option<&'static str> {
+        match self {
+            QuotaLevel::Normal => None,
+            QuotaLevel::Warning => Some("usage-warning"),
+            QuotaLevel::Critical => Some("usage-critical"),
+        }
+    }
+}
+
+/// Usage of the quota in the current billing period.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct QuotaUsage {
+    pub used: u64,
+    pub limit: u64,
+    pub level: QuotaLevel,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct UsageState {
+    boot_id: String,
+    /// Kernel counters at the last update keyed by interface index, only
+    /// meaningful within the boot they were read in. The index survives
+    /// renames of the interface.
+    counters: BTreeMap<String, Traffic>,
+    /// Last name of each interface index of this boot, the totals below
+    /// are keyed by name and move along when an interface is renamed.
+    names: BTreeMap<String, String>,
+    day: String,
+    daily: BTreeMap<String, Traffic>,
+    /// First day of the billing period.
+    period: String,
+    monthly: BTreeMap<String, Traffic>,
+    /// Highest quota level already notified in this period.
+    notified: QuotaLevel,
+}
+
+/// Daily and monthly traffic per interface, persisted under
+/// `$XDG_STATE_HOME/rgbar` so it survives restarts of the bar and reboots.
+pub struct DataUsage {
+    config: UsageConfig,
+    quota_interfaces: RegexSet,
+    path: Option<PathBuf>,
+    state: UsageState,
+    /// The saved counters are from a previous boot, the kernel counters of
+    /// interfaces seen for the first time then all count as new traffic.
+    rebooted: bool,
+    last_save: Instant,
+}
+
+impl DataUsage {
+    pub fn new(config: UsageConfig) -> Self {
+        let path = state_dir().map(|dir| dir.join("usage.toml"));
+        let mut state: UsageState = path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|content| {
+                toml::from_str(&content)
+                    .map_err(|err| log::error!("unable to parse data usage: {}", err))
+                    .ok()
+            })
+            .unwrap_or_default();
+
+        let boot_id = fs::read_to_string(BOOT_ID)
+            .map(|s| s.trim().to_owned())
+            .unwrap_or_default();
+        let rebooted = !state.boot_id.is_empty() && state.boot_id != boot_id;
+        if state.boot_id != boot_id {
+            state.counters.clear();
+            state.names.clear();
+            state.boot_id = boot_id;
+        }
+
+        let quota_interfaces = RegexSet::new(&config.quota_interfaces).unwrap_or_else(|err| {
+            log::error!("invalid quota interface pattern: {}", err);
+            RegexSet::empty()
+        });
+
+        Self {
+            config,
+            quota_interfaces,
+            path,
+            state,
+            rebooted,
+            last_save: Instant::now(),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// Account the kernel counters of an interface.
+    pub fn update(&mut self, ifindex: u32, interface: &str, rx: u64, tx: u64) {
+        if !self.config.enabled {
+            return;
+        }
+        self.roll_over();
+
+        let key = ifindex.to_string();
+        if let Some(old) = self.state.names.insert(key.clone(), interface.to_owned()) {
+            if old != interface {
+                self.rename(&old, interface);
+            }
+        }
+
+        let now = Traffic { rx, tx };
+        let last = self.state.counters.insert(key, now);
+        let traffic = match last {
+            // The counters start from zero again when a device is
+            // re-created with the same index.
+            Some(last) => Traffic {
+                rx: if rx >= last.rx { rx - last.rx } else { rx },
+                tx: if tx >= last.tx { tx - last.tx } else { tx },
+            },
+            None if self.rebooted => now,
+            // Started in this boot without knowing the counters, only
+            // count from now on.
+            None => Traffic::default(),
+        };
+
+        if traffic.total() == 0 {
+            return;
+        }
+        self.state
+            .daily
+            .entry(interface.to_owned())
+            .or_default()
+            .add(traffic);
+        self.state
+            .monthly
+            .entry(interface.to_owned())
+            .or_default()
+            .add(traffic);
+    }
+
+    /// Move the totals of `old` over to `new`.
+    fn rename(&mut self, old: &str, new: &str) {
+        for totals in [&mut self.state.daily, &mut self.state.monthly] {
+            if let Some(traffic) = totals.remove(old) {
+                totals.entry(new.to_owned()).or_default().add(traffic);
+            }
+        }
+    }
+
+    /// Traffic of today and of the billing period, summed over the
+    /// interfaces `counted` accepts.
+    pub fn usage(&self, counted: impl Fn(&str) -> bool) -> (Traffic, Traffic) {
+        let sum = |totals: &BTreeMap<String, Traffic>| {
+            let mut sum = Traffic::default();
+            totals
+                .iter()
+                .filter(|(interface, _)| counted(interface))
+                .for_each(|(_, traffic)| sum.add(*traffic));
+            sum
+        };
+        (sum(&self.state.daily), sum(&self.state.monthly))
+    }
+
+    pub fn quota(&self) -> Option<QuotaUsage> {
+        let limit = self.config.quota_mib? * 1024 * 1024;
+        let used = self
+            .state
+            .monthly
+            .iter()
+            .filter(|(interface, _)| {
+                self.quota_interfaces.is_empty() || self.quota_interfaces.is_match(interface)
+            })
+            .map(|(_, traffic)| traffic.total())
+            .sum();
+
+        let ratio = used as f64 / limit as f64;
+        let level = if ratio >= self.config.critical {
+            QuotaLevel::Critical
+        } else if ratio >= self.config.warning {
+            QuotaLevel::Warning
+        } else {
+            QuotaLevel::Normal
+        };
+
+        Some(QuotaUsage { used, limit, level })
+    }
+
+    /// Notify when the quota crossed a level and save the state now and
+    /// then, called once per sample.
+    pub fn flush(&mut self) {
+        if !self.config.enabled {
+            return;
+        }
+
+        if let Some(quota) = self.quota() {
+            if quota.level > self.state.notified {
+                notify_quota(&quota);
+                self.state.notified = quota.level;
+            }
+        }
+
+        if self.last_save.elapsed() >= SAVE_INTERVAL {
+            self.persist();
+            self.last_save = Instant::now();
+        }
+    }
+
+    /// Save the state right away, called on shutdown so the traffic since
+    /// the last periodic save is not lost.
+    pub fn persist(&self) {
+        if !self.config.enabled {
+            return;
+        }
+        if let Err(err) = self.save() {
+            log::error!("unable to save data usage: {}", err);
+        }
+    }
+
+    fn roll_over(&mut self) {
+        let today = Local::now().date_naive();
+
+        let day = today.to_string();
+        if self.state.day != day {
+            self.state.daily.clear();
+            self.state.day = day;
+        }
+
+        let period = period_start(today, self.config.billing_day).to_string();
+        if self.state.period != period {
+            self.state.monthly.clear();
+            self.state.notified = QuotaLevel::Normal;
+            self.state.period = period;
+        }
+    }
+
+    fn save(&self) -> AResult<()> {
+        let Some(path) = self.path.as_ref() else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        // Write aside and rename so a crash never leaves a truncated file.
+        let tmp = path.with_extension("toml.tmp");
+        fs::write(&tmp, toml::to_string(&self.state)?)?;
+        fs::rename(tmp, path)?;
+        Ok(())
+    }
+}
+
+/// The billing period starts on `billing_day` of this or the previous month.
+fn period_start(today: NaiveDate, billing_day: u32) -> NaiveDate {
+    // Every month has a 28th.
+    let billing_day = billing_day.clamp(1, 28);
+    let this_month = today.with_day(billing_day).unwrap_or(today);
+    if today.day() >= billing_day {
+        this_month
+    } else {
+        this_month
+            .checked_sub_months(Months::new(1))
+            .unwrap_or(this_month)
+    }
+}
+
+fn notify_quota(quota: &QuotaUsage) {
+    let urgency = match quota.level {
+        QuotaLevel::Critical => notify_rust::Urgency::Critical,
+        _ => notify_rust::Urgency::Normal,
+    };
+    let _ = Notification::new()
+        .summary("Data Usage")
+        .body(
+            format!(
+                "{} of {} used ({:.0}%)",
+                human_bytes::human_bytes(quota.used as f64),
+                human_bytes::human_bytes(quota.limit as f64),
+                quota.used as f64 * 100. / quota.limit as f64
+            )
+            .as_str(),
+        )
+        .icon("network-transmit-receive")
+        .urgency(urgency)
+        .timeout(Timeout::Milliseconds(6000))
+        .show();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn data_usage(rebooted: bool) -> DataUsage {
+        DataUsage {
+            config: UsageConfig::default(),
+            quota_interfaces: RegexSet::empty(),
+            path: None,
+            state: UsageState::default(),
+            rebooted,
+            last_save: Instant::now(),
+        }
+    }
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn period_start_this_and_previous_month() {
+        assert_eq!(period_start(date(2024, 5, 20), 15), date(2024, 5, 15));
+        assert_eq!(period_start(date(2024, 5, 15), 15), date(2024, 5, 15));
+        assert_eq!(period_start(date(2024, 5, 14), 15), date(2024, 4, 15));
+        // Over the turn of the year.
+        assert_eq!(period_start(date(2024, 1, 3), 15), date(2023, 12, 15));
+    }
+
+    #[test]
+    fn period_start_clamps_billing_day() {
+        assert_eq!(period_start(date(2024, 3, 10), 31), date(2024, 2, 28));
+        assert_eq!(period_start(date(2024, 3, 29), 31), date(2024, 3, 28));
+        assert_eq!(period_start(date(2024, 3, 10), 0), date(2024, 3, 1));
+    }
+
+    #[test]
+    fn counts_deltas_and_counter_resets() {
+        let mut usage = data_usage(false);
+
+        // The counters before the bar started are unknown.
+        usage.update(2, "eth0", 1000, 500);
+        assert_eq!(usage.usage(|_| true).0, Traffic::default());
+
+        usage.update(2, "eth0", 1300, 600);
+        assert_eq!(usage.usage(|_| true).0, Traffic { rx: 300, tx: 100 });
+
+        // The device was re-created and its counters started over.
+        usage.update(2, "eth0", 50, 20);
+        let (daily, monthly) = usage.usage(|_| true);
+        assert_eq!(daily, Traffic { rx: 350, tx: 120 });
+        assert_eq!(monthly, daily);
+    }
+
+    #[test]
+    fn counts_everything_after_reboot() {
+        let mut usage = data_usage(true);
+
+        usage.update(2, "eth0", 1000, 500);
+        usage.update(3, "wlan0", 10, 5);
+        assert_eq!(usage.usage(|_| true).0, Traffic { rx: 1010, tx: 505 });
+        assert_eq!(
+            usage.usage(|interface| interface == "wlan0").0,
+            Traffic { rx: 10, tx: 5 }
+        );
+    }
+
+    #[test]
+    fn moves_totals_on_rename() {
+        let mut usage = data_usage(true);
+
+        usage.update(2, "eth0", 1000, 500);
+        usage.update(2, "enp3s0", 1100, 600);
+
+        let (daily, monthly) = usage.usage(|interface| interface == "enp3s0");
+        assert_eq!(daily, Traffic { rx: 1100, tx: 600 });
+        assert_eq!(monthly, daily);
+        assert_eq!(
+            usage.usage(|interface| interface == "eth0").0,
+            Traffic::default()
+        );
+    }
+}
@@ -0,0 +1,255 @@
+use std::{fs, thread, time::Duration};
+
+use chin_tools::AResult;
+use glob::glob;
+use nix::poll::PollTimeout;
+
+use crate::config::read_config;
+use crate::prelude::*;
+use crate::util::fileutil::ProcFile;
+use crate::util::gtk_icon_loader::{self, load_fixed_status_surface, StatusName};
+use crate::util::procparse;
+use crate::window::WidgetShareInfo;
+
+use super::nl80211::{LinkInfo, Nl80211};
+use super::rtnl::RtnlEvents;
+use super::Block;
+
+const PROC_NET_WIRELESS: &str = "/proc/net/wireless";
+const WIRELESS_GLOB: &str = "/sys/class/net/*/wireless";
+// The signal has no notification, it is refreshed on this interval while
+// link changes wake us up right away.
+const REFRESH_MS: u16 = 2000;
+// Most drivers report the link quality out of 70.
+const MAX_QUALITY: f64 = 70.;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WirelessState {
+    pub interface: String,
+    /// Operational state of the interface is `up`.
+    pub up: bool,
+    /// Link quality in percent, from /proc/net/wireless.
+    pub quality: Option<f64>,
+    pub link: LinkInfo,
+}
+
+impl WirelessState {
+    pub fn connected(&self) -> bool {
+        self.up && self.link.ssid.is_some()
+    }
+
+    /// Signal in percent, the dBm level is used when the driver reports no
+    /// link quality.
+    pub fn signal_percent(&self) -> Option<f64> {
+        self.quality.or_else(|| {
+            self.link
+                .signal
+                .map(|dbm| (2. * (dbm as f64 + 100.)).clamp(0., 100.))
+        })
+    }
+
+    fn icon(&self) -> StatusName {
+        if !self.connected() {
+            return StatusName::WifiOff;
+        }
+
+        match self.signal_percent().unwrap_or_default() {
+            p if p >= 80. => StatusName::WifiSignal4,
+            p if p >= 60. => StatusName::WifiSignal3,
+            p if p >= 40. => StatusName::WifiSignal2,
+            p if p >= 20. => StatusName::WifiSignal1,
+            _ => StatusName::WifiSignal0,
+        }
+    }
+
+    fn tooltip(&self) -> String {
+        if !self.connected() {
+            return format!("{}: disconnected", self.interface);
+        }
+
+        let mut lines = vec![format!(
+            "{}: {}",
+            self.interface,
+            self.link.ssid.as_deref().unwrap_or_default()
+        )];
+        if let Some(bssid) = self.link.bssid.as_ref() {
+            lines.push(format!("bssid: {}", bssid));
+        }
+        if let Some(freq) = self.link.frequency {
+            lines.push(format!("frequency: {} MHz", freq));
+        }
+        match (self.link.signal, self.signal_percent()) {
+            (Some(dbm), Some(percent)) => {
+                lines.push(format!("signal: {} dBm ({:.0}%)", dbm, percent))
+            }
+            (None, Some(percent)) => lines.push(format!("signal: {:.0}%", percent)),
+            _ => {}
+        }
+        if let Some(bitrate) = self.link.bitrate {
+            lines.push(format!("bitrate: {:.1} Mbit/s", bitrate));
+        }
+
+        lines.join("\n")
+    }
+}
+
+#[derive(Clone)]
+pub enum WirelessIn {}
+
+#[derive(Clone)]
+pub enum WirelessOut {
+    State(WirelessState),
+}
+
+pub struct WirelessBlock {
+    dualchannel: DualChannel<WirelessOut, WirelessIn>,
+    interface: Option<String>,
+}
+
+impl WirelessBlock {
+    pub fn new() -> Self {
+        let interface = read_config(|c| c.wireless.interface.clone()).or_else(|| {
+            glob(WIRELESS_GLOB)
+                .ok()?
+                .map_while(Result::ok)
+                .find_map(|p| p.parent()?.file_name()?.to_str().map(str::to_owned))
+        });
+
+        WirelessBlock {
+            dualchannel: DualChannel::new(30),
+            interface,
+        }
+    }
+
+    fn read_state(
+        interface: &str,
+        nl80211: Option<&mut Nl80211>,
+        wireless: Option<&mut ProcFile>,
+    ) -> WirelessState {
+        let sysfs =
+            |attr: &str| fs::read_to_string(format!("/sys/class/net/{}/{}", interface, attr));
+        let up = sysfs("operstate").is_ok_and(|s| s.trim() == "up");
+
+        let mut state = WirelessState {
+            interface: interface.to_owned(),
+            up,
+            ..Default::default()
+        };
+        if !up {
+            return state;
+        }
+
+        if let Some(Ok(content)) = wireless.map(|f| f.read()) {
+            state.quality = procparse::wireless(content)
+                .find(|(name, ..)| *name == interface)
+                .map(|(_, link, _)| (link * 100. / MAX_QUALITY).clamp(0., 100.));
+        }
+
+        // The index changes when the interface is re-created.
+        let ifindex = sysfs("ifindex")
+            .ok()
+            .and_then(|s| procparse::number::<u32>(&s));
+        if let (Some(nl80211), Some(ifindex)) = (nl80211, ifindex) {
+            match nl80211.link_info(ifindex) {
+                Ok(link) => state.link = link,
+                Err(err) => log::debug!("unable to query nl80211 for {}: {}", interface, err),
+            }
+        }
+
+        state
+    }
+}
+
+impl Block for WirelessBlock {
+    type Out = WirelessOut;
+    type In = WirelessIn;
+
+    fn run(&mut self) -> AResult<()> {
+        let Some(interface) = self.interface.clone() else {
+            return Ok(());
+        };
+
+        let sender = self.dualchannel.get_out_sender();
+        thread::spawn(move || {
+            let mut events = RtnlEvents::subscribe(&[libc::RTNLGRP_LINK])
+                .map_err(|err| log::warn!("unable to watch links, fallback to polling: {}", err))
+                .ok();
+            let mut nl80211 = Nl80211::connect()
+                .map_err(|err| log::warn!("nl80211 is not available: {}", err))
+                .ok();
+            let mut wireless = ProcFile::open(PROC_NET_WIRELESS).ok();
+
+            loop {
+                let state = Self::read_state(&interface, nl80211.as_mut(), wireless.as_mut());
+                sender.send(WirelessOut::State(state)).unwrap();
+
+                let waited = events
+                    .as_mut()
+                    .map(|events| events.wait(PollTimeout::from(REFRESH_MS)));
+                match waited {
+                    Some(Ok(_)) => {}
+                    Some(Err(err)) => {
+                        log::error!("unable to wait for link events: {}", err);
+                        events = None;
+                    }
+                    None => thread::sleep(Duration::from_millis(REFRESH_MS as u64)),
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    fn widget(&self, _share_info: &WidgetShareInfo) -> gtk::Widget {
+        let holder = gtk::Box::builder()
+            .orientation(gtk::Orientation::Horizontal)
+            .hexpand(false)
+            .build();
+
+        if self.interface.is_none() {
+            holder.set_no_show_all(true);
+            return holder.upcast();
+        }
+
+        let icon = gtk_icon_loader::load_fixed_status_image(StatusName::WifiOff);
+        let label = gtk::Label::builder().build();
+        label.style_context().add_class("wireless-label");
+
+        holder.pack_start(&icon, false, false, 0);
+        holder.pack_start(&label, false, false, 0);
+
+        let block = holder.clone();
+        let mut receiver = self.dualchannel.get_out_receiver();
+        MainContext::ref_thread_default().spawn_local(async move {
+            let mut last_icon = StatusName::WifiOff;
+            loop {
+                if let Ok(msg) = receiver.recv().await {
+                    match msg {
+                        WirelessOut::State(state) => {
+                            let mapped = state.icon();
+                            if mapped != last_icon {
+                                icon.set_from_surface(
+                                    load_fixed_status_surface(mapped.clone()).as_ref(),
+                                );
+                                last_icon = mapped;
+                            }
+
+                            label.set_label(state.link.ssid.as_deref().unwrap_or_default());
+                            label.set_visible(state.connected());
+                            block.set_tooltip_text(Some(state.tooltip().as_str()));
+
+                            let style = block.style_context();
+                            if state.connected() {
+                                style.remove_class("wireless-disconnected");
+                            } else {
+                                style.add_class("wireless-disconnected");
+                            }
+                        }
+                    }
+                }
+            }
+        });
+
+        holder.upcast()
+    }
+}
@@ -8,6 +8,7 @@ use std::{
 use arc_swap::ArcSwap;
 use chin_tools::{aanyhow, AResult, EResult};
 use serde::{Deserialize, Serialize};
+use smart_default::SmartDefault;
 
 lazy_static::lazy_static! {
     static ref CONFIG: ArcSwap<Option<ParsedConfig>> = ArcSwap::new(Arc::new(None));
@@ -17,6 +18,15 @@ pub fn get_config() -> Arc<Option<ParsedConfig>> {
     CONFIG.load().clone()
 }
 
+/// Run `f` against the loaded config, falling back to the defaults when no
+/// config has been loaded.
+pub fn read_config<T>(f: impl FnOnce(&Config) -> T) -> T {
+    match get_config().as_ref() {
+        Some(parsed) => f(&parsed.config),
+        None => f(&Config::default()),
+    }
+}
+
 pub fn set_config() -> EResult {
     let config = Config::read_from_toml_file(None::<PathBuf>)?;
     CONFIG.store(Arc::new(Some(config)));
@@ -29,9 +39,270 @@ pub struct IconConfig {
     pub alias: HashMap<String, Vec<String>>,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Default, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FrequencyDisplay {
+    #[default]
+    Avg,
+    Max,
+}
+
+#[derive(Debug, Clone, Deserialize, SmartDefault, Serialize)]
+#[serde(default)]
+pub struct CpuConfig {
+    pub frequency: FrequencyDisplay,
+    /// hwmon sensors tried in order for the cpu temperature, as `chip/label`,
+    /// `chip` or `label`.
+    #[default(vec![
+        "coretemp/Package id 0".to_owned(),
+        "k10temp/Tctl".to_owned(),
+        "zenpower/Tctl".to_owned(),
+        "cpu_thermal".to_owned(),
+    ])]
+    pub temp_sensors: Vec<String>,
+}
+
+/// Selects the interfaces a netspeed block counts. When `allow` is set only
+/// interfaces matching one of its regexes are counted, otherwise every
+/// interface not matching `deny`.
+#[derive(Debug, Clone, Deserialize, SmartDefault, Serialize)]
+#[serde(default)]
+pub struct InterfaceFilter {
+    pub allow: Vec<String>,
+    #[default(default_interface_deny())]
+    pub deny: Vec<String>,
+}
+
+fn default_interface_deny() -> Vec<String> {
+    [
+        "^lo$",
+        // Created by python-based bandwidth manager "traffictoll".
+        "^ifb[0-9]+",
+        // Created by lxd container manager.
+        "^lxdbr[0-9]+",
+        "^virbr[0-9]+",
+        "^br[0-9]+",
+        "^vnet[0-9]+",
+        "^tap[0-9]+",
+        // Container side of docker/podman networks, already seen on the bridge.
+        "^veth",
+        "^docker[0-9]+",
+    ]
+    .into_iter()
+    .map(str::to_owned)
+    .collect()
+}
+
+/// Daily and monthly traffic accounting of the netspeed blocks.
+#[derive(Debug, Clone, Deserialize, SmartDefault, Serialize)]
+#[serde(default)]
+pub struct UsageConfig {
+    #[default(true)]
+    pub enabled: bool,
+    /// Traffic allowed per billing period, no quota when unset.
+    pub quota_mib: Option<u64>,
+    /// Day of the month the billing period starts on, 1 to 28.
+    #[default(1)]
+    pub billing_day: u32,
+    /// Interfaces counted against the quota, all of them when empty.
+    pub quota_interfaces: Vec<String>,
+    /// Used fraction of the quota that adds the `usage-warning` class.
+    #[default(0.8)]
+    pub warning: f64,
+    /// Used fraction of the quota that adds the `usage-critical` class.
+    #[default(0.95)]
+    pub critical: f64,
+}
+
+#[derive(Debug, Clone, Deserialize, Default, Serialize)]
+#[serde(default)]
+pub struct NetspeedConfig {
+    /// One netspeed block per filter, a single block with the default
+    /// filter when empty.
+    pub blocks: Vec<InterfaceFilter>,
+    pub usage: UsageConfig,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Default, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertUrgency {
+    Low,
+    #[default]
+    Normal,
+    Critical,
+}
+
+/// Where the battery block reads its state from.
+#[derive(Debug, Clone, Copy, Deserialize, Default, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BatteryBackend {
+    /// Poll /sys/class/power_supply.
+    #[default]
+    Sysfs,
+    /// Follow UPower over the system bus, sysfs is used when it is not
+    /// running.
+    Upower,
+}
+
+/// A low battery notification, fired while discharging once the percentage
+/// or the estimated minutes left drop to the given value.
+#[derive(Debug, Clone, Deserialize, SmartDefault, Serialize)]
+#[serde(default)]
+pub struct BatteryAlert {
+    pub percent: Option<u8>,
+    pub minutes: Option<u64>,
+    pub urgency: AlertUrgency,
+    /// `{percent}` and `{remain}` are replaced.
+    #[default("Battery at {percent}% ({remain} left)".to_owned())]
+    pub message: String,
+    /// Seconds between repeats while the level holds, fired once when 0.
+    pub repeat_secs: u64,
+    /// Run once when the alert fires, e.g. `["systemctl", "hibernate"]`.
+    pub command: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, SmartDefault, Serialize)]
+#[serde(default)]
+pub struct BatteryConfig {
+    pub backend: BatteryBackend,
+    #[default(default_battery_alerts())]
+    pub alerts: Vec<BatteryAlert>,
+    /// Notify once charging reaches this percentage.
+    pub charged_percent: Option<u8>,
+    /// Notify when the charger is plugged or unplugged.
+    pub notify_plug: bool,
+    /// End thresholds offered in the charge limit menu, 100 charges fully.
+    #[default(vec![60, 80, 100])]
+    pub charge_limits: Vec<u8>,
+    /// Days of charge history kept for the battery popup.
+    #[default(7)]
+    pub history_days: u32,
+}
+
+fn default_battery_alerts() -> Vec<BatteryAlert> {
+    vec![BatteryAlert {
+        percent: Some(30),
+        urgency: AlertUrgency::Critical,
+        message: "Connect the adapter...\nRemain {percent}% ({remain})".to_owned(),
+        repeat_secs: 300,
+        ..Default::default()
+    }]
+}
+
+#[derive(Debug, Clone, Deserialize, SmartDefault, Serialize)]
+#[serde(default)]
+pub struct PowerProfileConfig {
+    #[default(true)]
+    pub enabled: bool,
+    /// Bus power-profiles-daemon is on: `system`, `session` or an address
+    /// like `unix:path=/run/ppd.sock`, handy for a mock service.
+    #[default("system".to_owned())]
+    pub bus: String,
+}
+
+#[derive(Debug, Clone, Deserialize, SmartDefault, Serialize)]
+#[serde(default)]
+pub struct AudioConfig {
+    /// Move the playing streams along when another output is chosen in
+    /// the switcher of the volume block.
+    #[default(true)]
+    pub move_streams: bool,
+    /// Volume change per scroll notch, in percent.
+    #[default(3)]
+    pub step: u32,
+    /// Highest volume scrolling and the mixer go to, in percent.
+    #[default(150)]
+    pub max_volume: u32,
+    /// Above 100% the sound is amplified in software and may clip, the
+    /// volume stops at 100% without this whatever `max_volume` says.
+    #[default(true)]
+    pub allow_overamplification: bool,
+    /// Highest volume scrolling goes to on the microphone, in percent.
+    #[default(100)]
+    pub mic_max_volume: u32,
+}
+
+impl AudioConfig {
+    /// Highest volume allowed, in percent.
+    pub fn volume_limit(&self) -> u32 {
+        match self.allow_overamplification {
+            true => self.max_volume,
+            false => self.max_volume.min(100),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, SmartDefault, Serialize)]
+#[serde(default)]
+pub struct LatencyConfig {
+    /// One latency block per target: `gateway`, `dns`, a host pinged over
+    /// ICMP or a `host:port` timed with a TCP connect.
+    pub targets: Vec<String>,
+    #[default(2000)]
+    pub interval_ms: u64,
+    /// Probes without an answer after this long count as lost.
+    #[default(1000)]
+    pub timeout_ms: u64,
+    /// Number of probes the loss and min/avg/max are computed over.
+    #[default(30)]
+    pub window: usize,
+    /// Round trip time at the top of the chart.
+    #[default(200.)]
+    pub chart_max_ms: f64,
+}
+
+#[derive(Debug, Clone, Deserialize, Default, Serialize)]
+#[serde(default)]
+pub struct WirelessConfig {
+    /// Interface shown by the wireless block, the first one with a
+    /// `wireless` directory in sysfs when unset.
+    pub interface: Option<String>,
+}
+
 #[derive(Debug, Clone, Deserialize, Default, Serialize)]
+#[serde(default)]
+pub struct SensorsConfig {
+    /// hwmon sensors shown by the sensors block, the block is hidden when
+    /// none is configured.
+    pub sensors: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, SmartDefault, Serialize)]
+#[serde(default)]
+pub struct PsiConfig {
+    /// avg10 stall percentage that adds the `psi-warning` class.
+    #[default(10.)]
+    pub warning: f64,
+    /// avg10 stall percentage that adds the `psi-critical` class.
+    #[default(30.)]
+    pub critical: f64,
+    /// Wake up on PSI triggers instead of reading every second.
+    pub triggers: bool,
+    /// Stall time within a two seconds window that fires a trigger.
+    #[default(200)]
+    pub trigger_stall_ms: u64,
+}
+
+#[derive(Debug, Clone, Deserialize, SmartDefault, Serialize)]
+#[serde(default)]
 pub struct Config {
     pub icon_path: String,
+    /// Command used to write kernel attributes we have no permission for,
+    /// e.g. `["pkexec"]` or `["sudo", "-n"]`.
+    #[default(vec!["pkexec".to_owned()])]
+    pub privileged_helper: Vec<String>,
+    /// Rows shown in the top processes popup of the cpu and memory blocks.
+    #[default(10)]
+    pub top_processes: usize,
+    pub cpu: CpuConfig,
+    pub psi: PsiConfig,
+    pub sensors: SensorsConfig,
+    pub netspeed: NetspeedConfig,
+    pub wireless: WirelessConfig,
+    pub latency: LatencyConfig,
+    pub battery: BatteryConfig,
+    pub power_profile: PowerProfileConfig,
+    pub audio: AudioConfig,
 }
 
 #[derive(Debug, Clone)]
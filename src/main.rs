@@ -42,6 +42,15 @@ fn main() -> EResult {
 
         RGBApplication::monitor_monitors(&screen, app).unwrap();
     });
+    // Quit through the application so the blocks get to save their state.
+    for signal in [libc::SIGTERM, libc::SIGINT, libc::SIGHUP] {
+        let application = application.clone();
+        gtk::glib::unix_signal_add_local(signal, move || {
+            application.quit();
+            ControlFlow::Break
+        });
+    }
+
     info!("hold on.");
     let _holder = application.hold();
 
@@ -1,15 +1,53 @@
 use std::{
+    env,
     fs::File,
-    io::{self, BufRead},
-    path::Path,
+    io,
+    os::unix::fs::FileExt,
+    path::{Path, PathBuf},
 };
 
-// The output is wrapped in a Result to allow matching on errors
-// Returns an Iterator to the Reader of the lines of the file.
-pub fn read_lines<P>(filename: P) -> io::Result<io::Lines<io::BufReader<File>>>
-where
-    P: AsRef<Path>,
-{
-    let file = File::open(filename)?;
-    Ok(io::BufReader::new(file).lines())
+/// A /proc or /sys file opened once and re-read from offset zero with pread
+/// on every sample. The kernel regenerates the content on each read at the
+/// start of the file, and the buffer is kept between reads so sampling does
+/// not allocate once it has grown to the file size.
+pub struct ProcFile {
+    file: File,
+    buf: Vec<u8>,
+}
+
+impl ProcFile {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Self {
+            file: File::open(path)?,
+            buf: vec![0; 4096],
+        })
+    }
+
+    /// Read the current content of the file.
+    pub fn read(&mut self) -> io::Result<&str> {
+        let mut len = 0;
+        loop {
+            if len == self.buf.len() {
+                self.buf.resize(len * 2, 0);
+            }
+            match self.file.read_at(&mut self.buf[len..], len as u64) {
+                Ok(0) => break,
+                Ok(n) => len += n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            }
+        }
+
+        std::str::from_utf8(&self.buf[..len])
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+}
+
+/// Where state kept across restarts lives, `$XDG_STATE_HOME/rgbar`.
+pub fn state_dir() -> Option<PathBuf> {
+    let base = match env::var("XDG_STATE_HOME") {
+        Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var("HOME").ok()?).join(".local/state"),
+    };
+    Some(base.join("rgbar"))
 }
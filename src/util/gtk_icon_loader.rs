@@ -24,8 +24,19 @@ impl std::fmt::Debug for GtkIconLoader {
 #[allow(dead_code)]
 pub enum StatusName {
     CPU,
+    CpuBoostOn,
+    CpuBoostOff,
     RAM,
     WIFI,
+    WifiOff,
+    WifiSignal0,
+    WifiSignal1,
+    WifiSignal2,
+    WifiSignal3,
+    WifiSignal4,
+    Pressure,
+    Thermometer,
+    Fan,
 
     BatteryFull,
     BatteryHigh,
@@ -43,6 +54,10 @@ pub enum StatusName {
     BatteryConservationOff,
     BatteryConservationUnknown,
 
+    PowerSaver,
+    PowerBalanced,
+    PowerPerformance,
+
     Headphone,
     Headset,
 
@@ -50,6 +65,9 @@ pub enum StatusName {
     VolumeMedium,
     VolumeLow,
     VolumeMute,
+
+    Microphone,
+    MicrophoneMute,
 }
 
 impl GtkIconLoader {
@@ -145,8 +163,19 @@ fn load_fixed_status_pixbuf(status_name: StatusName) -> Pixbuf {
 
     match status_name {
         StatusName::CPU => include_surface!("cpu", BASE_SIZE, BASE_SIZE),
+        StatusName::CpuBoostOn => include_surface!("cpu-boost-on", BASE_SIZE, BASE_SIZE),
+        StatusName::CpuBoostOff => include_surface!("cpu-boost-off", BASE_SIZE, BASE_SIZE),
         StatusName::RAM => include_surface!("memory", BASE_SIZE * 6 / 5, BASE_SIZE * 6 / 5),
         StatusName::WIFI => include_surface!("wifi", BASE_SIZE, BASE_SIZE),
+        StatusName::WifiOff => include_surface!("wifi-off", BASE_SIZE, BASE_SIZE),
+        StatusName::WifiSignal0 => include_surface!("wifi-signal-0", BASE_SIZE, BASE_SIZE),
+        StatusName::WifiSignal1 => include_surface!("wifi-signal-1", BASE_SIZE, BASE_SIZE),
+        StatusName::WifiSignal2 => include_surface!("wifi-signal-2", BASE_SIZE, BASE_SIZE),
+        StatusName::WifiSignal3 => include_surface!("wifi-signal-3", BASE_SIZE, BASE_SIZE),
+        StatusName::WifiSignal4 => include_surface!("wifi-signal-4", BASE_SIZE, BASE_SIZE),
+        StatusName::Pressure => include_surface!("pressure", BASE_SIZE, BASE_SIZE),
+        StatusName::Thermometer => include_surface!("thermometer", BASE_SIZE, BASE_SIZE),
+        StatusName::Fan => include_surface!("fan", BASE_SIZE, BASE_SIZE),
         StatusName::BatteryFull => {
             include_surface!("battery-full", BASE_SIZE * 11 / 7, BASE_SIZE)
         }
@@ -184,6 +213,11 @@ fn load_fixed_status_pixbuf(status_name: StatusName) -> Pixbuf {
         StatusName::BatteryConservationUnknown => {
             include_surface!("battery-not-conser", BASE_SIZE, BASE_SIZE)
         }
+        StatusName::PowerSaver => include_surface!("power-saver", BASE_SIZE, BASE_SIZE),
+        StatusName::PowerBalanced => include_surface!("power-balanced", BASE_SIZE, BASE_SIZE),
+        StatusName::PowerPerformance => {
+            include_surface!("power-performance", BASE_SIZE, BASE_SIZE)
+        }
         StatusName::Headphone => {
             include_surface!("audio-headphone", BASE_SIZE, BASE_SIZE)
         }
@@ -202,6 +236,10 @@ fn load_fixed_status_pixbuf(status_name: StatusName) -> Pixbuf {
         StatusName::VolumeMute => {
             include_surface!("audio-volume-muted", BASE_SIZE, BASE_SIZE)
         }
+        StatusName::Microphone => include_surface!("microphone", BASE_SIZE, BASE_SIZE),
+        StatusName::MicrophoneMute => {
+            include_surface!("microphone-muted", BASE_SIZE, BASE_SIZE)
+        }
     }
 }
 
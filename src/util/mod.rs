@@ -1,4 +1,6 @@
 pub mod fileutil;
 pub mod gdk_util;
 pub mod gtk_icon_loader;
+pub mod privileged;
+pub mod procparse;
 pub mod timeutil;
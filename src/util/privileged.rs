@@ -0,0 +1,58 @@
+use std::{
+    fs,
+    io::ErrorKind,
+    path::PathBuf,
+    process::{Command, Stdio},
+    thread,
+};
+
+use chin_tools::{aanyhow, AResult};
+
+use crate::config::read_config;
+
+// Run by the helper as `sh -c SCRIPT rgbar path1 value1 path2 value2 ...`,
+// so paths and values never get interpreted by the shell.
+const WRITE_SCRIPT: &str =
+    r#"while [ "$#" -gt 1 ]; do printf '%s' "$2" > "$1" || exit 1; shift 2; done"#;
+
+/// Write values into kernel attributes under /sys or /proc.
+///
+/// Attributes we can write are written in place, the rest are handed to the
+/// configured privileged helper in one background call, so the user sees at
+/// most one authentication prompt and the GTK thread never waits for it.
+pub fn write_attrs(attrs: Vec<(PathBuf, String)>) -> AResult<()> {
+    let mut denied = vec![];
+    for (path, value) in attrs {
+        match fs::write(&path, &value) {
+            Ok(_) => {}
+            Err(err) if err.kind() == ErrorKind::PermissionDenied => denied.push((path, value)),
+            Err(err) => Err(aanyhow!("unable to write {:?}: {}", path, err))?,
+        }
+    }
+
+    if denied.is_empty() {
+        return Ok(());
+    }
+
+    let helper = read_config(|c| c.privileged_helper.clone());
+    let (program, args) = helper
+        .split_first()
+        .ok_or(aanyhow!("no privileged helper configured"))?;
+
+    let mut command = Command::new(program);
+    command
+        .args(args)
+        .args(["sh", "-c", WRITE_SCRIPT, "rgbar"])
+        .stdin(Stdio::null());
+    for (path, value) in denied {
+        command.arg(path).arg(value);
+    }
+
+    thread::spawn(move || match command.status() {
+        Ok(status) if status.success() => {}
+        Ok(status) => log::error!("privileged helper exited with {}", status),
+        Err(err) => log::error!("unable to run privileged helper: {}", err),
+    });
+
+    Ok(())
+}
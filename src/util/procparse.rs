@@ -0,0 +1,212 @@
+//! Parsers for the /proc and /sys formats sampled by the blocks.
+//!
+//! Everything here works on borrowed slices of a buffer read by
+//! [`super::fileutil::ProcFile`] and never allocates, so it can run every
+//! second without touching the heap. The module only depends on `std` so the
+//! benchmarks can include it directly.
+
+use std::str::FromStr;
+
+/// Jiffies of one `cpu` line of /proc/stat.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CpuTime {
+    pub idle: usize,
+    pub non_idle: usize,
+    pub user: usize,
+    pub system_total: usize,
+}
+
+impl CpuTime {
+    /// Parse the columns following the `cpuN` name.
+    pub fn parse(s: &str) -> Option<Self> {
+        let mut s = s.split_ascii_whitespace();
+        let mut next = || usize::from_str(s.next()?).ok();
+        let user = next()?;
+        let nice = next()?;
+        let system = next()?;
+        let idle = next()?;
+        let iowait = next()?;
+        let irq = next()?;
+        let softirq = next()?;
+
+        let system_total = nice + system + irq + softirq;
+        Some(Self {
+            idle: idle + iowait,
+            non_idle: user + system_total,
+            user,
+            system_total,
+        })
+    }
+
+    pub fn utilization(&self, old: Self) -> f64 {
+        let elapsed = (self.idle + self.non_idle).saturating_sub(old.idle + old.non_idle);
+        if elapsed == 0 {
+            0.0
+        } else {
+            (self.non_idle.saturating_sub(old.non_idle) as f64 / elapsed as f64).clamp(0., 1.)
+        }
+    }
+
+    pub fn utilization_user_and_system(&self, old: Self) -> (f64, f64) {
+        let elapsed = (self.idle + self.non_idle).saturating_sub(old.idle + old.non_idle);
+        if elapsed == 0 {
+            (0.0, 0.)
+        } else {
+            (
+                (self.user.saturating_sub(old.user) as f64 / elapsed as f64).clamp(0., 1.),
+                (self.system_total.saturating_sub(old.system_total) as f64 / elapsed as f64)
+                    .clamp(0., 1.),
+            )
+        }
+    }
+}
+
+/// Parse /proc/stat, returning the aggregated `cpu` line and filling `cores`
+/// with one entry per `cpuN` line. `cores` is cleared first so the caller can
+/// keep reusing its capacity.
+pub fn proc_stat(content: &str, cores: &mut Vec<CpuTime>) -> Option<CpuTime> {
+    cores.clear();
+    let mut total = None;
+
+    // The cpu lines come first, stop at the first other line.
+    for line in content.lines() {
+        let Some(rest) = line.strip_prefix("cpu") else {
+            break;
+        };
+        let (name, data) = rest.split_once(' ')?;
+        if name.is_empty() {
+            total = CpuTime::parse(data);
+        } else if let Some(time) = CpuTime::parse(data) {
+            cores.push(time);
+        }
+    }
+
+    total
+}
+
+/// Iterate the `Name: value [kB]` lines of /proc/meminfo.
+pub fn meminfo(content: &str) -> impl Iterator<Item = (&str, usize)> {
+    content.lines().filter_map(|line| {
+        let (name, rest) = line.split_once(':')?;
+        let value = rest.split_ascii_whitespace().next()?;
+        Some((name, usize::from_str(value).ok()?))
+    })
+}
+
+/// Iterate the `name type data` lines of a kstat file like
+/// /proc/spl/kstat/zfs/arcstats, skipping the headers.
+pub fn kstat(content: &str) -> impl Iterator<Item = (&str, usize)> {
+    content.lines().filter_map(|line| {
+        let mut words = line.split_ascii_whitespace();
+        let name = words.next()?;
+        let value = words.nth(1)?;
+        Some((name, usize::from_str(value).ok()?))
+    })
+}
+
+/// Iterate the interfaces of /proc/net/dev with their received and
+/// transmitted bytes.
+pub fn net_dev(content: &str) -> impl Iterator<Item = (&str, usize, usize)> {
+    // The first two lines are headers without a colon.
+    content.lines().filter_map(|line| {
+        let (interface, counters) = line.split_once(':')?;
+        let mut fields = counters.split_ascii_whitespace();
+        let download = usize::from_str(fields.next()?).ok()?;
+        // Skip the other seven receive columns.
+        let upload = usize::from_str(fields.nth(7)?).ok()?;
+        Some((interface.trim(), download, upload))
+    })
+}
+
+/// Parse a sysfs attribute holding a single number.
+pub fn number<T: FromStr>(content: &str) -> Option<T> {
+    T::from_str(content.trim()).ok()
+}
+
+/// Iterate the whitespace separated numbers of a sysfs attribute such as
+/// /sys/block/zram0/mm_stat, unparsable columns read as zero.
+pub fn numbers(content: &str) -> impl Iterator<Item = usize> + '_ {
+    content
+        .split_ascii_whitespace()
+        .map(|f| usize::from_str(f).unwrap_or_default())
+}
+
+/// Iterate the interfaces of /proc/net/wireless with their link quality and
+/// signal level.
+pub fn wireless(content: &str) -> impl Iterator<Item = (&str, f64, f64)> {
+    content.lines().filter_map(|line| {
+        let (interface, fields) = line.split_once(':')?;
+        // Values are printed with a trailing dot when they were updated
+        // since the last read, e.g. `54.`.
+        let mut fields = fields
+            .split_ascii_whitespace()
+            .map(|f| f64::from_str(f.trim_end_matches('.')).ok());
+        let _status = fields.next()?;
+        let link = fields.next()??;
+        let level = fields.next()??;
+        Some((interface.trim(), link, level))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_proc_stat() {
+        let mut cores = vec![CpuTime::default(); 4];
+        let total = proc_stat(include_str!("../../benches/fixtures/stat"), &mut cores).unwrap();
+
+        // user nice system idle iowait irq softirq: 35545 0 4465 110094 280 0 3
+        assert_eq!(total.user, 35545);
+        assert_eq!(total.system_total, 4468);
+        assert_eq!(total.idle, 110374);
+        assert_eq!(total.non_idle, 40013);
+        assert_eq!(cores.len(), 1);
+        assert_eq!(cores[0].idle, total.idle);
+    }
+
+    #[test]
+    fn test_meminfo() {
+        let content = include_str!("../../benches/fixtures/meminfo");
+        let value = |name: &str| meminfo(content).find(|(n, _)| *n == name).map(|(_, v)| v);
+
+        assert_eq!(value("MemTotal"), Some(6158152));
+        assert_eq!(value("MemAvailable"), Some(5633976));
+        assert_eq!(value("Buffers"), Some(74676));
+        assert_eq!(value("Missing"), None);
+    }
+
+    #[test]
+    fn test_net_dev() {
+        let interfaces: Vec<_> = net_dev(include_str!("../../benches/fixtures/net_dev")).collect();
+
+        assert_eq!(
+            interfaces,
+            vec![
+                ("lo", 23698643, 23698643),
+                ("ifb0", 0, 0),
+                ("ifb1", 0, 0),
+                ("eth0", 2898, 4023),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_kstat() {
+        let content = include_str!("../../benches/fixtures/arcstats");
+        let mut stats = kstat(content);
+
+        // Both header lines are skipped.
+        assert_eq!(stats.next(), Some(("hits", 112386418)));
+        assert_eq!(stats.next(), Some(("iohits", 284419)));
+        assert_eq!(
+            kstat(content).find(|(name, _)| *name == "misses"),
+            Some(("misses", 1254810))
+        );
+        assert_eq!(
+            kstat(content).find(|(name, _)| *name == "size"),
+            Some(("size", 6489024736))
+        );
+    }
+}
@@ -0,0 +1,195 @@
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
+
+use chrono::Local;
+
+use crate::blocks::battery::history::{History, Sample, SAMPLE_INTERVAL};
+use crate::blocks::battery::{BatteryInfo, PowerStatus};
+use crate::prelude::*;
+
+/// Time spans the chart can show, in seconds.
+const RANGES: [(&str, i64); 3] = [("6h", 6 * 3600), ("24h", 24 * 3600), ("7d", 7 * 24 * 3600)];
+
+/// A popover with the health and identity of the battery and a chart of
+/// its charge level history.
+#[derive(Clone)]
+pub struct BatteryPopup {
+    popover: gtk::Popover,
+    health: Label,
+    cycles: Label,
+    identity: Label,
+    drawing_area: gtk::DrawingArea,
+}
+
+impl BatteryPopup {
+    pub fn new(relative_to: &impl IsA<Widget>, history: Rc<RefCell<History>>) -> Self {
+        let grid = gtk::Grid::builder()
+            .column_spacing(8)
+            .row_spacing(2)
+            .build();
+        grid.style_context().add_class("battery-popup");
+
+        let value_label = || Label::builder().xalign(0.).build();
+        let health = value_label();
+        let cycles = value_label();
+        let identity = value_label();
+        for (i, (title, value)) in [
+            ("Health", &health),
+            ("Cycles", &cycles),
+            ("Battery", &identity),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let label = Label::builder().label(title).xalign(0.).build();
+            label.style_context().add_class("battery-popup-header");
+            grid.attach(&label, 0, i as i32, 1, 1);
+            grid.attach(value, 1, i as i32, 1, 1);
+        }
+
+        let drawing_area = gtk::DrawingArea::builder()
+            .width_request(360)
+            .height_request(120)
+            .build();
+        drawing_area.style_context().add_class("battery-history");
+        grid.attach(&drawing_area, 0, 3, 2, 1);
+
+        let range = Rc::new(Cell::new(RANGES[1].1));
+        let ranges = gtk::Box::new(Orientation::Horizontal, 4);
+        let mut group: Option<gtk::RadioButton> = None;
+        for (name, secs) in RANGES {
+            let button = gtk::RadioButton::with_label(name);
+            button.join_group(group.as_ref());
+            button.set_active(secs == range.get());
+            button.connect_toggled(clone!(@strong range, @strong drawing_area => move |b| {
+                if b.is_active() {
+                    range.set(secs);
+                    drawing_area.queue_draw();
+                }
+            }));
+            ranges.pack_start(&button, false, false, 0);
+            group.get_or_insert(button);
+        }
+        grid.attach(&ranges, 0, 4, 2, 1);
+        grid.show_all();
+
+        drawing_area.connect_draw(move |da, cr| {
+            Self::draw(&history.borrow(), range.get(), da, cr);
+            Propagation::Proceed
+        });
+
+        let popover = gtk::Popover::builder()
+            .relative_to(relative_to)
+            .child(&grid)
+            .position(gtk::PositionType::Bottom)
+            .build();
+
+        Self {
+            popover,
+            health,
+            cycles,
+            identity,
+            drawing_area,
+        }
+    }
+
+    pub fn toggle(&self) {
+        if self.popover.is_visible() {
+            self.popover.popdown();
+        } else {
+            self.popover.popup();
+        }
+    }
+
+    pub fn update(&self, battery: &BatteryInfo) {
+        let health = match battery.energy_full_design {
+            0 => "unknown".to_owned(),
+            design => format!(
+                "{:.0}% ({:.1} of {:.1} Wh)",
+                battery.energy_full as f64 * 100. / design as f64,
+                battery.energy_full as f64 / 1_000_000.,
+                design as f64 / 1_000_000.
+            ),
+        };
+        self.health.set_label(&health);
+
+        // Many drivers report 0 when they do not count cycles.
+        let cycles = match battery.cycle_count {
+            0 => "unknown".to_owned(),
+            count => count.to_string(),
+        };
+        self.cycles.set_label(&cycles);
+
+        let identity: Vec<&str> = [
+            battery.manufacturer.as_str(),
+            battery.model_name.as_str(),
+            battery.technology.as_str(),
+        ]
+        .into_iter()
+        .filter(|s| !s.is_empty() && *s != "Unknown")
+        .collect();
+        self.identity.set_label(&match identity.is_empty() {
+            true => battery.name.clone(),
+            false => format!("{} ({})", identity.join(" "), battery.name),
+        });
+
+        if self.popover.is_visible() {
+            self.drawing_area.queue_draw();
+        }
+    }
+
+    fn draw(history: &History, range: i64, da: &gtk::DrawingArea, cr: &gtk::cairo::Context) {
+        let alloc = da.allocation();
+        let (w, h) = (alloc.width() as f64, alloc.height() as f64);
+
+        let now = Local::now().timestamp();
+        let x = |time: i64| w - (now - time) as f64 / range as f64 * w;
+        let y = |percent: u8| h - percent as f64 / 100. * h;
+
+        cr.set_line_width(1.);
+        cr.set_source_rgba(0.5, 0.5, 0.5, 0.3);
+        for percent in [25, 50, 75] {
+            cr.move_to(0., y(percent));
+            cr.line_to(w, y(percent));
+        }
+        let _ = cr.stroke();
+
+        let samples: Vec<&Sample> = history
+            .samples()
+            .iter()
+            .filter(|s| s.time >= now - range - SAMPLE_INTERVAL)
+            .collect();
+
+        // Shade charge and discharge periods behind the line.
+        for pair in samples.windows(2) {
+            let (from, to) = (pair[0], pair[1]);
+            if to.time - from.time > 2 * SAMPLE_INTERVAL {
+                continue;
+            }
+            match from.status {
+                PowerStatus::Charging => cr.set_source_rgba(0.3, 0.8, 0.3, 0.25),
+                PowerStatus::Discharging => cr.set_source_rgba(0.9, 0.5, 0.2, 0.15),
+                _ => continue,
+            }
+            cr.rectangle(x(from.time), 0., x(to.time) - x(from.time), h);
+            let _ = cr.fill();
+        }
+
+        // Gaps are times the machine was off or asleep, leave them blank.
+        cr.set_line_width(1.5);
+        cr.set_source_rgb(0.9, 0.9, 0.9);
+        let mut last: Option<&Sample> = None;
+        for sample in samples {
+            match last {
+                Some(last) if sample.time - last.time <= 2 * SAMPLE_INTERVAL => {
+                    cr.line_to(x(sample.time), y(sample.percent))
+                }
+                _ => cr.move_to(x(sample.time), y(sample.percent)),
+            }
+            last = Some(sample);
+        }
+        let _ = cr.stroke();
+    }
+}
@@ -0,0 +1,207 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::BTreeMap,
+    rc::Rc,
+};
+
+use crate::blocks::audio::pulse::{SinkDescription, SinkInput};
+use crate::blocks::audio::PulseBM;
+use crate::config::read_config;
+use crate::datahodler::channel::SSender;
+use crate::prelude::*;
+use crate::util::gtk_icon_loader::GtkIconLoader;
+
+struct MixerRow {
+    container: gtk::Box,
+    name: Label,
+    scale: gtk::Scale,
+    mute: gtk::CheckButton,
+    sinks: gtk::ComboBoxText,
+    /// Names of the sinks the combo box was filled with.
+    sink_names: RefCell<Vec<String>>,
+    /// Set while the widgets follow the server, so that does not echo back.
+    updating: Rc<Cell<bool>>,
+}
+
+impl MixerRow {
+    fn new(input: &SinkInput, sender: &SSender<PulseBM>, icon_loader: &GtkIconLoader) -> Self {
+        let index = input.index;
+        let updating = Rc::new(Cell::new(false));
+
+        let icon = gtk::Image::new();
+        let pixbuf = [input.icon_name.as_ref(), input.binary.as_ref()]
+            .into_iter()
+            .flatten()
+            .find_map(|name| icon_loader.load_named_pixbuf(name));
+        match pixbuf {
+            Some(pixbuf) => {
+                icon.set_from_surface(pixbuf.create_surface(2, None::<&Window>).as_ref())
+            }
+            None => icon.set_from_icon_name(
+                Some(input.icon_name.as_deref().unwrap_or("audio-x-generic")),
+                gtk::IconSize::LargeToolbar,
+            ),
+        }
+
+        let name = Label::builder()
+            .xalign(0.)
+            .width_chars(16)
+            .max_width_chars(16)
+            .ellipsize(EllipsizeMode::End)
+            .build();
+
+        let limit = read_config(|c| c.audio.volume_limit());
+        let scale = gtk::Scale::with_range(Orientation::Horizontal, 0., limit as f64, 1.);
+        scale.set_digits(0);
+        scale.set_value_pos(gtk::PositionType::Right);
+        scale.set_width_request(140);
+        if limit > 100 {
+            scale.add_mark(100., gtk::PositionType::Bottom, None);
+        }
+        scale.connect_value_changed(clone!(@strong sender, @strong updating => move |s| {
+            if !updating.get() {
+                let _ = sender.send_blocking(PulseBM::SetAppVolume(index, s.value().round() as u32));
+            }
+        }));
+
+        let mute = gtk::CheckButton::with_label("Mute");
+        mute.connect_toggled(clone!(@strong sender, @strong updating => move |b| {
+            if !updating.get() {
+                let _ = sender.send_blocking(PulseBM::SetAppMute(index, b.is_active()));
+            }
+        }));
+
+        let sinks = gtk::ComboBoxText::new();
+        sinks.connect_changed(clone!(@strong sender, @strong updating => move |combo| {
+            if let (false, Some(sink)) = (updating.get(), combo.active_id()) {
+                let _ = sender.send_blocking(PulseBM::MoveApp(index, sink.to_string()));
+            }
+        }));
+
+        let container = gtk::Box::new(Orientation::Horizontal, 6);
+        container.pack_start(&icon, false, false, 0);
+        container.pack_start(&name, false, false, 0);
+        container.pack_start(&scale, true, true, 0);
+        container.pack_start(&mute, false, false, 0);
+        container.pack_start(&sinks, false, false, 0);
+        container.show_all();
+
+        Self {
+            container,
+            name,
+            scale,
+            mute,
+            sinks,
+            sink_names: Default::default(),
+            updating,
+        }
+    }
+
+    fn update(&self, input: &SinkInput, sinks: &[SinkDescription]) {
+        self.updating.set(true);
+
+        self.name.set_label(&input.app_name);
+        self.container.set_tooltip_text(Some(&input.app_name));
+        // Leave a slider being dragged alone, the server echoes every step
+        // it was set to and would pull it back.
+        if !self.scale.has_grab() && self.scale.value().round() as u32 != input.volume {
+            self.scale.set_value(input.volume as f64);
+        }
+        self.mute.set_active(input.muted);
+
+        let names: Vec<String> = sinks.iter().map(|sink| sink.name.clone()).collect();
+        if *self.sink_names.borrow() != names {
+            self.sinks.remove_all();
+            for sink in sinks {
+                self.sinks.append(Some(&sink.name), &sink.description);
+            }
+            self.sink_names.replace(names);
+        }
+        let current = sinks.iter().find(|sink| sink.index == input.sink);
+        self.sinks
+            .set_active_id(current.map(|sink| sink.name.as_str()));
+        // Nowhere to move to.
+        self.sinks.set_visible(sinks.len() > 1);
+
+        self.updating.set(false);
+    }
+}
+
+/// A popover with a volume slider, mute and output of every playing
+/// application.
+#[derive(Clone)]
+pub struct MixerPopup {
+    popover: gtk::Popover,
+    list: gtk::Box,
+    empty: Label,
+    rows: Rc<RefCell<BTreeMap<u32, MixerRow>>>,
+    sinks: Rc<RefCell<Vec<SinkDescription>>>,
+    sender: SSender<PulseBM>,
+    icon_loader: GtkIconLoader,
+}
+
+impl MixerPopup {
+    pub fn new(relative_to: &impl IsA<Widget>, sender: SSender<PulseBM>) -> Self {
+        let list = gtk::Box::new(Orientation::Vertical, 2);
+        list.style_context().add_class("mixer-popup");
+
+        let empty = Label::new(Some("Nothing is playing"));
+        list.pack_start(&empty, false, false, 0);
+        list.show_all();
+
+        let popover = gtk::Popover::builder()
+            .relative_to(relative_to)
+            .child(&list)
+            .position(gtk::PositionType::Bottom)
+            .build();
+
+        Self {
+            popover,
+            list,
+            empty,
+            rows: Default::default(),
+            sinks: Default::default(),
+            sender,
+            icon_loader: GtkIconLoader::new(),
+        }
+    }
+
+    pub fn toggle(&self) {
+        if self.popover.is_visible() {
+            self.popover.popdown();
+        } else {
+            self.popover.popup();
+        }
+    }
+
+    /// Used for the outputs a stream can be moved to, from the next
+    /// `update` on.
+    pub fn update_sinks(&self, sinks: &[SinkDescription]) {
+        self.sinks.replace(sinks.to_vec());
+    }
+
+    pub fn update(&self, inputs: &[SinkInput]) {
+        let sinks = self.sinks.borrow();
+        let mut rows = self.rows.borrow_mut();
+
+        // Rows are kept across updates, a rebuilt slider would drop the
+        // drag in progress.
+        rows.retain(|index, row| {
+            let playing = inputs.iter().any(|input| input.index == *index);
+            if !playing {
+                self.list.remove(&row.container);
+            }
+            playing
+        });
+        for input in inputs {
+            let row = rows.entry(input.index).or_insert_with(|| {
+                let row = MixerRow::new(input, &self.sender, &self.icon_loader);
+                self.list.pack_start(&row.container, false, false, 0);
+                row
+            });
+            row.update(input, &sinks);
+        }
+
+        self.empty.set_visible(inputs.is_empty());
+    }
+}
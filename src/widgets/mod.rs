@@ -1 +1,5 @@
+pub mod battery_popup;
 pub mod chart;
+pub mod mixer_popup;
+pub mod process_popup;
+pub mod sink_popup;
@@ -0,0 +1,186 @@
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
+
+use gtk::glib::SourceId;
+use human_bytes::human_bytes;
+use nix::sys::signal::Signal;
+
+use crate::blocks::process::{self, ProcessSampler, ProcessSort};
+use crate::prelude::*;
+
+struct ProcessRow {
+    name: Label,
+    pid: Label,
+    cpu: Label,
+    rss: Label,
+    buttons: gtk::Box,
+    /// Pid and name of the process shown in the row.
+    target: Rc<RefCell<(i32, String)>>,
+}
+
+impl ProcessRow {
+    fn new(grid: &gtk::Grid, row: i32) -> Self {
+        let name = Label::builder()
+            .xalign(0.)
+            .max_width_chars(24)
+            .ellipsize(EllipsizeMode::End)
+            .build();
+        let pid = Label::builder().xalign(1.).build();
+        let cpu = Label::builder().xalign(1.).build();
+        let rss = Label::builder().xalign(1.).build();
+        let buttons = gtk::Box::new(Orientation::Horizontal, 2);
+        let target: Rc<RefCell<(i32, String)>> = Default::default();
+
+        for (text, sig) in [("TERM", Signal::SIGTERM), ("KILL", Signal::SIGKILL)] {
+            let button = gtk::Button::with_label(text);
+            button.style_context().add_class("process-signal");
+            button.connect_clicked(clone!(@strong target => move |_| {
+                let (pid, name) = target.borrow().clone();
+                // The pid may have been reused since the row was drawn.
+                if process::process_name(pid).as_ref() != Some(&name) {
+                    log::warn!("process {} ({}) is gone, not sending {}", pid, name, sig);
+                    return;
+                }
+                if let Err(err) = process::send_signal(pid, sig) {
+                    log::error!("unable to send {} to {}: {}", sig, pid, err);
+                }
+            }));
+            buttons.pack_start(&button, false, false, 0);
+        }
+
+        grid.attach(&name, 0, row, 1, 1);
+        grid.attach(&pid, 1, row, 1, 1);
+        grid.attach(&cpu, 2, row, 1, 1);
+        grid.attach(&rss, 3, row, 1, 1);
+        grid.attach(&buttons, 4, row, 1, 1);
+
+        Self {
+            name,
+            pid,
+            cpu,
+            rss,
+            buttons,
+            target,
+        }
+    }
+
+    fn set_visible(&self, visible: bool) {
+        for widget in [
+            self.name.upcast_ref::<Widget>(),
+            self.pid.upcast_ref(),
+            self.cpu.upcast_ref(),
+            self.rss.upcast_ref(),
+            self.buttons.upcast_ref(),
+        ] {
+            widget.set_visible(visible);
+        }
+    }
+}
+
+/// A popover listing the heaviest processes. Processes are only sampled
+/// while it is open.
+#[derive(Clone)]
+pub struct ProcessPopup {
+    popover: gtk::Popover,
+}
+
+impl ProcessPopup {
+    pub fn new(relative_to: &impl IsA<Widget>, sort: ProcessSort, count: usize) -> Self {
+        let grid = gtk::Grid::builder()
+            .column_spacing(8)
+            .row_spacing(2)
+            .build();
+        grid.style_context().add_class("process-popup");
+
+        for (i, title) in ["Name", "PID", "CPU", "Memory"].into_iter().enumerate() {
+            let label = Label::builder().label(title).xalign(0.).build();
+            label.style_context().add_class("process-header");
+            grid.attach(&label, i as i32, 0, 1, 1);
+        }
+
+        let rows: Rc<Vec<ProcessRow>> = Rc::new(
+            (1..=count as i32)
+                .map(|i| ProcessRow::new(&grid, i))
+                .collect(),
+        );
+        grid.show_all();
+
+        // Rows keep their order while the pointer is over them, so a button
+        // is not clicked after another process moved into its row.
+        let hovered = Rc::new(Cell::new(false));
+        let holder = EventBox::builder().child(&grid).build();
+        holder.add_events(EventMask::ENTER_NOTIFY_MASK | EventMask::LEAVE_NOTIFY_MASK);
+        holder.connect_enter_notify_event(clone!(@strong hovered => move |_, _| {
+            hovered.set(true);
+            Propagation::Proceed
+        }));
+        holder.connect_leave_notify_event(clone!(@strong hovered => move |_, event| {
+            // Moving onto a button is not leaving.
+            if event.detail() != gtk::gdk::NotifyType::Inferior {
+                hovered.set(false);
+            }
+            Propagation::Proceed
+        }));
+        holder.show_all();
+
+        let popover = gtk::Popover::builder()
+            .relative_to(relative_to)
+            .child(&holder)
+            .position(gtk::PositionType::Bottom)
+            .build();
+
+        let source: Rc<RefCell<Option<SourceId>>> = Default::default();
+
+        popover.connect_map(clone!(@strong source => move |_| {
+            let mut sampler = ProcessSampler::new();
+            Self::refresh(&rows, &mut sampler, sort);
+            hovered.set(false);
+
+            let id = timeout_add_seconds_local(1, clone!(@strong rows, @strong hovered => move || {
+                if !hovered.get() {
+                    Self::refresh(&rows, &mut sampler, sort);
+                }
+                ControlFlow::Continue
+            }));
+            if let Some(old) = source.replace(Some(id)) {
+                old.remove();
+            }
+        }));
+
+        popover.connect_unmap(move |_| {
+            if let Some(id) = source.take() {
+                id.remove();
+            }
+        });
+
+        Self { popover }
+    }
+
+    pub fn toggle(&self) {
+        if self.popover.is_visible() {
+            self.popover.popdown();
+        } else {
+            self.popover.popup();
+        }
+    }
+
+    fn refresh(rows: &[ProcessRow], sampler: &mut ProcessSampler, sort: ProcessSort) {
+        let samples = process::top(sampler.sample(), sort, rows.len());
+
+        for (i, row) in rows.iter().enumerate() {
+            match samples.get(i) {
+                Some(sample) => {
+                    row.target.replace((sample.pid, sample.name.clone()));
+                    row.name.set_label(&sample.name);
+                    row.pid.set_label(&sample.pid.to_string());
+                    row.cpu.set_label(&format!("{:.1}%", sample.cpu));
+                    row.rss.set_label(&human_bytes(sample.rss as f64));
+                    row.set_visible(true);
+                }
+                None => row.set_visible(false),
+            }
+        }
+    }
+}
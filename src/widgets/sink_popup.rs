@@ -0,0 +1,98 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::blocks::audio::pulse::SinkDescription;
+use crate::blocks::audio::PulseBM;
+use crate::datahodler::channel::SSender;
+use crate::prelude::*;
+
+/// A popover listing the outputs, to choose the default one and the port
+/// it plays on.
+#[derive(Clone)]
+pub struct SinkPopup {
+    popover: gtk::Popover,
+    list: gtk::Box,
+    sender: SSender<PulseBM>,
+    /// Sinks and default the list was built for.
+    shown: Rc<RefCell<(Vec<SinkDescription>, String)>>,
+}
+
+impl SinkPopup {
+    pub fn new(relative_to: &impl IsA<Widget>, sender: SSender<PulseBM>) -> Self {
+        let list = gtk::Box::new(Orientation::Vertical, 2);
+        list.style_context().add_class("sink-popup");
+
+        let popover = gtk::Popover::builder()
+            .relative_to(relative_to)
+            .child(&list)
+            .position(gtk::PositionType::Bottom)
+            .build();
+
+        Self {
+            popover,
+            list,
+            sender,
+            shown: Default::default(),
+        }
+    }
+
+    pub fn toggle(&self) {
+        if self.popover.is_visible() {
+            self.popover.popdown();
+        } else {
+            self.popover.popup();
+        }
+    }
+
+    pub fn update(&self, sinks: &[SinkDescription], default: &str) {
+        {
+            let shown = self.shown.borrow();
+            if shown.0 == sinks && shown.1 == default {
+                return;
+            }
+        }
+        self.shown.replace((sinks.to_vec(), default.to_owned()));
+
+        self.list.foreach(|child| self.list.remove(child));
+
+        let mut group: Option<gtk::RadioButton> = None;
+        for sink in sinks {
+            let button = gtk::RadioButton::with_label(&sink.description);
+            button.join_group(group.as_ref());
+            button.set_active(sink.name == default);
+            let sender = self.sender.clone();
+            let name = sink.name.clone();
+            button.connect_toggled(move |b| {
+                if b.is_active() {
+                    let _ = sender.send_blocking(PulseBM::SetDefaultSink(name.clone()));
+                }
+            });
+            self.list.pack_start(&button, false, false, 0);
+            group.get_or_insert(button);
+
+            // Speakers and headphones are often ports of the same sink.
+            if sink.ports.len() > 1 {
+                let ports = gtk::ComboBoxText::new();
+                ports.style_context().add_class("sink-ports");
+                for port in sink.ports.iter() {
+                    let label = match port.available {
+                        true => port.description.clone(),
+                        false => format!("{} (unplugged)", port.description),
+                    };
+                    ports.append(Some(&port.name), &label);
+                }
+                ports.set_active_id(sink.active_port.as_deref());
+                let sender = self.sender.clone();
+                let name = sink.name.clone();
+                ports.connect_changed(move |combo| {
+                    if let Some(port) = combo.active_id() {
+                        let _ = sender
+                            .send_blocking(PulseBM::SetSinkPort(name.clone(), port.to_string()));
+                    }
+                });
+                self.list.pack_start(&ports, false, false, 0);
+            }
+        }
+
+        self.list.show_all();
+    }
+}
@@ -69,21 +69,53 @@ impl RGBWindow {
         battery.style_context().add_class("block");
         bar.pack_end(&battery, false, false, 0);
 
+        let power_profile = bm.power_profile_block.widget(share_info);
+        power_profile.style_context().add_class("block");
+        bar.pack_end(&power_profile, false, false, 0);
+
         let volume = bm.vol_block.widget(share_info);
         volume.style_context().add_class("block");
         bar.pack_end(&volume, false, false, 0);
 
+        let mic = bm.mic_block.widget(share_info);
+        mic.style_context().add_class("block");
+        bar.pack_end(&mic, false, false, 0);
+
         let cpu = bm.cpu_block.widget(share_info);
         cpu.style_context().add_class("block");
         bar.pack_end(&cpu, false, false, 0);
 
+        let sensors = bm.sensors_block.widget(share_info);
+        sensors.style_context().add_class("block");
+        bar.pack_end(&sensors, false, false, 0);
+
+        let psi = bm.psi_block.widget(share_info);
+        psi.style_context().add_class("block");
+        bar.pack_end(&psi, false, false, 0);
+
         let memory = bm.memory_block.widget(share_info);
         memory.style_context().add_class("block");
         bar.pack_end(&memory, false, false, 0);
 
-        let netspeed = bm.net_block.widget(share_info);
-        netspeed.style_context().add_class("block");
-        bar.pack_end(&netspeed, false, false, 0);
+        for (net_block, address_block) in bm.net_blocks.iter().zip(bm.address_blocks.iter()) {
+            let netspeed = net_block.widget(share_info);
+            netspeed.style_context().add_class("block");
+            bar.pack_end(&netspeed, false, false, 0);
+
+            let address = address_block.widget(share_info);
+            address.style_context().add_class("block");
+            bar.pack_end(&address, false, false, 0);
+        }
+
+        let wireless = bm.wireless_block.widget(share_info);
+        wireless.style_context().add_class("block");
+        bar.pack_end(&wireless, false, false, 0);
+
+        for latency_block in bm.latency_blocks.iter() {
+            let latency = latency_block.widget(share_info);
+            latency.style_context().add_class("block");
+            bar.pack_end(&latency, false, false, 0);
+        }
 
         let wayland = bm.wayland_block.widget(share_info);
         bar.pack_start(&wayland, false, false, 0);